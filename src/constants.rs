@@ -1,9 +1,14 @@
 pub const G: f64 = 6.67430e-11_f64; //gravitational constant
+pub const SPEED_OF_LIGHT: f64 = 299_792_458.0; //in m/s
+pub const DRAG_COEFFICIENT: f64 = 2.2; //typical value for satellites in free molecular flow
+pub const LUMINOUS_EFFICACY: f64 = 98.0; //lm/W of sunlight, turns the intensity of a light source into its radiated power
 pub const DEF_M_TO_UNIT: f64 = 0.0000001;
 pub const M_TO_AU: f32 = 6.684587e-12_f32;
 
 pub const HOUR_IN_SECONDS: f32 = 60.0 * 60.0;
 pub const DAY_IN_SECONDS: f32 = HOUR_IN_SECONDS * 24.0;
 
-pub const DEFAULT_TIMESTEP: f64 = 60.0 * 15.0; //15mins
-pub const DEFAULT_SUB_STEPS: i32 = 4 * 24; //DEFAULT_TIMESTEP * DEFAULT_SUB_STEPS = 1 day/s
\ No newline at end of file
+//The speed is the simulated time per real second that every sub step adds, a single sub step integrates speed / PHYSICS_TICK_RATE
+pub const DEFAULT_TIMESTEP: f64 = 60.0 * 15.0; //15mins/s, so 15s per sub step
+pub const DEFAULT_SUB_STEPS: i32 = 4 * 24; //DEFAULT_TIMESTEP * DEFAULT_SUB_STEPS = 1 day/s, in PHYSICS_TICK_RATE physics steps
+pub const PHYSICS_TICK_RATE: f64 = 60.0; //fixed physics updates per second, independent of the frame rate
\ No newline at end of file
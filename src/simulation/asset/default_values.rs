@@ -25,6 +25,10 @@ pub fn default_rot_matrix() -> SerializedMat3 {
     SerializedMat3::from(Mat3::IDENTITY)
 }
 
+pub fn default_reflectivity() -> f64 {
+    1.0
+}
+
 pub fn default_color() -> String {
     "#ffffff".to_string()
 }
\ No newline at end of file
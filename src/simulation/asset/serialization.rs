@@ -1,4 +1,6 @@
 use crate::simulation::asset::default_values::*;
+use crate::simulation::components::body::{Atmosphere, RadiationPressure, ZonalHarmonics};
+use crate::simulation::components::collision::CollisionMode;
 use anise::structure::planetocentric::ellipsoid::Ellipsoid;
 use bevy::asset::io::Reader;
 use bevy::prelude::{Asset, AssetApp, Mat3, Vec3};
@@ -19,6 +21,16 @@ pub struct SimulationData {
     pub description: String,
     pub scale: f32,
     pub timestep: i32,
+    #[serde(default)]
+    pub softening: f64, //in km
+    #[serde(default)]
+    pub collisions: CollisionMode,
+    #[serde(default)]
+    pub roche_breakup: bool,
+    #[serde(default)]
+    pub relativity: bool, //first order post-Newtonian correction around the most massive body
+    #[serde(default)]
+    pub barycentric_correction: bool, //shifts the starting data into the barycentric frame when the simulation is loaded
 }
 
 #[derive(Debug, Deserialize, Serialize, TypePath, Clone)]
@@ -111,7 +123,115 @@ pub struct SerializedBodyData {
     pub ellipsoid: Ellipsoid,
     pub light_source: Option<SerializedLightSource>,
     #[serde(default = "default_rot_matrix")]
-    pub rotation_matrix: SerializedMat3
+    pub rotation_matrix: SerializedMat3,
+    #[serde(default)]
+    pub test_particle: bool,
+    #[serde(default)]
+    pub ephemeris_driven: bool,
+    #[serde(default)]
+    pub zonal_harmonics: Option<SerializedZonalHarmonics>,
+    #[serde(default)]
+    pub radiation_pressure: Option<SerializedRadiationPressure>,
+    #[serde(default)]
+    pub atmosphere: Option<SerializedAtmosphere>
+}
+
+#[derive(Debug, Serialize, Deserialize, TypePath, Clone, Copy)]
+pub struct SerializedZonalHarmonics {
+    #[serde(default)]
+    pub j2: f64,
+    #[serde(default)]
+    pub j3: f64,
+    #[serde(default)]
+    pub j4: f64
+}
+
+impl From<ZonalHarmonics> for SerializedZonalHarmonics {
+
+    fn from(value: ZonalHarmonics) -> Self {
+        SerializedZonalHarmonics {
+            j2: value.j2,
+            j3: value.j3,
+            j4: value.j4
+        }
+    }
+
+}
+
+impl From<SerializedZonalHarmonics> for ZonalHarmonics {
+
+    fn from(value: SerializedZonalHarmonics) -> Self {
+        ZonalHarmonics {
+            j2: value.j2,
+            j3: value.j3,
+            j4: value.j4
+        }
+    }
+
+}
+
+#[derive(Debug, Serialize, Deserialize, TypePath, Clone, Copy)]
+pub struct SerializedRadiationPressure {
+    pub area: f64, //in m²
+    #[serde(default = "default_reflectivity")]
+    pub reflectivity: f64
+}
+
+impl From<RadiationPressure> for SerializedRadiationPressure {
+
+    fn from(value: RadiationPressure) -> Self {
+        SerializedRadiationPressure {
+            area: value.area,
+            reflectivity: value.reflectivity
+        }
+    }
+
+}
+
+impl From<SerializedRadiationPressure> for RadiationPressure {
+
+    fn from(value: SerializedRadiationPressure) -> Self {
+        RadiationPressure {
+            area: value.area,
+            reflectivity: value.reflectivity
+        }
+    }
+
+}
+
+#[derive(Debug, Serialize, Deserialize, TypePath, Clone, Copy)]
+pub struct SerializedAtmosphere {
+    pub base_density: f64, //in kg/m³ at the reference altitude
+    #[serde(default)]
+    pub reference_altitude: f64, //in km, 0 for the surface
+    pub scale_height: f64, //in km
+    pub top: f64 //in km
+}
+
+impl From<Atmosphere> for SerializedAtmosphere {
+
+    fn from(value: Atmosphere) -> Self {
+        SerializedAtmosphere {
+            base_density: value.base_density,
+            reference_altitude: value.reference_altitude / 1000.0,
+            scale_height: value.scale_height / 1000.0,
+            top: value.top / 1000.0
+        }
+    }
+
+}
+
+impl From<SerializedAtmosphere> for Atmosphere {
+
+    fn from(value: SerializedAtmosphere) -> Self {
+        Atmosphere {
+            base_density: value.base_density,
+            reference_altitude: value.reference_altitude * 1000.0,
+            scale_height: value.scale_height * 1000.0,
+            top: value.top * 1000.0
+        }
+    }
+
 }
 
 #[derive(Debug, Serialize, Deserialize, TypePath, Clone)]
@@ -1,10 +1,14 @@
+use crate::simulation::components::body::EphemerisDriven;
 use crate::simulation::components::horizons::AniseMetadata;
+use crate::simulation::integration::{paused, set_step_span, PhysicsStep, StepSpan};
 use crate::simulation::components::selection::SelectedEntity;
 use crate::simulation::scenario::loading::LoadingState;
 use crate::simulation::scenario::setup::ScenarioData;
 use crate::simulation::ui::editor_body_panel::EditorPanelState;
 use crate::simulation::ui::scenario_selection::SelectionState;
-use crate::simulation::ui::toast::{error_toast, success_toast, ToastContainer};
+use crate::simulation::ui::toast::{error_toast, success_toast, warning_toast, ToastContainer};
+use crate::simulation::ui::SimTime;
+use crate::utils::sim_state_type_simulation;
 use crate::simulation::{SimState, SimStateType};
 use anise::constants::frames::SSB_J2000;
 use anise::constants::orientations::J2000;
@@ -13,9 +17,9 @@ use anise::naif::daf::DAF;
 use anise::naif::spk::summary::SPKSummaryRecord;
 use anise::prelude::{Almanac, Epoch, Frame, SPK};
 use anise::structure::PlanetaryDataSet;
-use bevy::app::Plugin;
+use bevy::app::{FixedUpdate, Plugin};
 use bevy::math::DVec3;
-use bevy::prelude::{IntoScheduleConfigs, Name, Query, Res, ResMut, Resource, State, Update};
+use bevy::prelude::{not, Commands, Entity, IntoScheduleConfigs, Name, Query, Res, ResMut, Resource, State, Update, With};
 use bevy_async_task::TaskPool;
 use std::fs;
 
@@ -33,7 +37,8 @@ impl Plugin for AnisePlugin{
     fn build(&self, app: &mut bevy::prelude::App) {
         app
             .init_resource::<AlmanacHolder>()
-            .add_systems(Update, spk_file_loading.run_if(loading_or_editor));
+            .add_systems(Update, spk_file_loading.run_if(loading_or_editor))
+            .add_systems(FixedUpdate, (check_ephemeris_coverage).after(set_step_span).before(PhysicsStep).run_if(sim_state_type_simulation).run_if(not(paused)));
     }
 }
 
@@ -96,6 +101,33 @@ pub fn retrieve_starting_data(
     }
 }
 
+//The integrators place the ephemeris driven bodies at every force evaluation of the step and leave them out otherwise.
+//Bodies the kernels don't cover for the whole step are integrated from then on
+fn check_ephemeris_coverage(
+    bodies: Query<(Entity, &Name, &AniseMetadata), With<EphemerisDriven>>,
+    almanac: Res<AlmanacHolder>,
+    scenario: Res<ScenarioData>,
+    sim_time: Res<SimTime>,
+    span: Res<StepSpan>,
+    mut toasts: ResMut<ToastContainer>,
+    mut commands: Commands,
+) {
+    for (entity, name, metadata) in &bodies {
+        let state = [sim_time.0, sim_time.0 + span.0].into_iter()
+            .map(|seconds| almanac.0.translate(Frame::new(metadata.ephemeris_id, J2000), SSB_J2000, epoch_at(&scenario, seconds), None))
+            .find_map(|state| state.err());
+        if let Some(e) = state {
+            toasts.0.add(warning_toast(format!("Couldn't retrieve the ephemeris of {}, it is integrated from now on: {:?}", name, e).as_str()));
+            commands.entity(entity).remove::<EphemerisDriven>();
+        }
+    }
+}
+
+//The epoch a number of simulated seconds after the start of the scenario
+pub fn epoch_at(scenario: &ScenarioData, seconds: f64) -> Epoch {
+    Epoch::from_unix_milliseconds(scenario.starting_time_millis as f64 + seconds * 1000.0)
+}
+
 fn matrix3_to_mat3(m: anise::math::Matrix3) -> bevy::math::Mat3 {
     bevy::math::Mat3::from_cols(
         bevy::math::Vec3::new(m.data.0[0][0] as f32, m.data.0[0][1] as f32, m.data.0[0][2] as f32),
@@ -104,7 +136,7 @@ fn matrix3_to_mat3(m: anise::math::Matrix3) -> bevy::math::Mat3 {
     )
 }
 
-fn vector3_to_dvec3(v: Vector3) -> DVec3 {
+pub fn vector3_to_dvec3(v: Vector3) -> DVec3 {
     DVec3::new(v.x, v.y, v.z)
 }
 
@@ -116,11 +148,17 @@ fn spk_file_loading(
     mut task_pool: TaskPool<Result<AlmanacType, Error>>,
     sim_type: Res<SimStateType>,
     selection_state: Res<SelectionState>,
+    ephemeris_bodies: Query<(), With<EphemerisDriven>>,
 ) {
     if loading_state.loaded_spice_files || !loading_state.spawned_bodies {
         return;
     }
-    if *sim_type != SimStateType::Editor || scenario_data.spice_files.is_empty() || (loading_state.spice_loaded > 0 && loading_state.spice_loaded == loading_state.spice_total) || (!selection_state.auto_load_spk && !loading_state.force_reload) {
+    //a simulation needs the kernels for the ephemeris driven bodies, otherwise they are only used to track the divergence
+    let wanted = match *sim_type {
+        SimStateType::Editor => selection_state.auto_load_spk || loading_state.force_reload,
+        _ => selection_state.auto_load_spk || !ephemeris_bodies.is_empty()
+    };
+    if !wanted || scenario_data.spice_files.is_empty() || (loading_state.spice_loaded > 0 && loading_state.spice_loaded == loading_state.spice_total) {
         loading_state.loaded_spice_files = true;
         loading_state.force_reload = false;
         return;
@@ -1,8 +1,14 @@
-use crate::simulation::components::body::{BodyChildren, Moon, Planet, SimPosition, Star};
-use crate::simulation::integration::{paused, SimulationStep};
+use std::f64::consts::{PI, TAU};
+
+use crate::constants::{DAY_IN_SECONDS, G};
+use crate::simulation::components::body::{BodyParent, Mass, SimPosition, Velocity};
+use crate::simulation::components::orbital_elements::OrbitalElements;
+use crate::simulation::integration::{advance_sim_time, paused};
+use crate::simulation::ui::SimTime;
 use crate::utils::sim_state_type_simulation;
-use bevy::prelude::{not, Transform};
-use bevy::{math::DVec3, prelude::{App, Component, Entity, IntoScheduleConfigs, Plugin, Query, Reflect, Update, With, Without}};
+use bevy::app::FixedUpdate;
+use bevy::prelude::{not, Res};
+use bevy::{math::DVec3, prelude::{App, Component, IntoScheduleConfigs, Plugin, Query, Reflect}};
 
 pub struct ApsisPlugin;
 
@@ -11,7 +17,7 @@ impl Plugin for ApsisPlugin {
     fn build(&self, app: &mut App) {
         app
             .register_type::<Apsis>()
-            .add_systems(Update, (update_apsis.after(SimulationStep)).run_if(sim_state_type_simulation).run_if(not(paused)));
+            .add_systems(FixedUpdate, (update_apsis).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
     }
 
 }
@@ -20,69 +26,196 @@ impl Plugin for ApsisPlugin {
 pub struct Apsis {
 
     pub position: DVec3,
-    pub distance: f32
+    pub distance: f64
 
 }
 
+//Apsides of the osculating orbit around the BodyParent and the passages through them.
+//All times are simulation seconds like SimTime
 #[derive(Component, Debug, Clone, Copy, Reflect, Default)]
 pub struct ApsisBody {
 
-    pub aphelion: Apsis,
-    pub perihelion: Apsis,
+    pub periapsis: Option<Apsis>,
+    pub apoapsis: Option<Apsis>, //only for bound orbits
+    pub next_periapsis: Option<f64>, //seconds until the next passage on the current orbit
+    pub next_apoapsis: Option<f64>,
+    pub last_periapsis: Option<f64>, //time of the latest passage
+    pub last_apoapsis: Option<f64>,
+    pub precession: Precession,
+    radial_velocity: Option<f64>,
+
+}
+
+impl ApsisBody {
+
+    //The radial velocity changes its sign at the apsides, the mean anomaly then tells how long ago the passage was
+    fn update(&mut self, elements: &OrbitalElements, parent_position: DVec3, mu: f64, radial_velocity: f64, time: f64) {
+        let rotation = elements.perifocal_rotation();
+        let direction = rotation * DVec3::X;
+        self.periapsis = elements.periapsis_distance().map(|distance| Apsis { position: parent_position + direction * distance, distance });
+        self.apoapsis = elements.apoapsis_distance().map(|distance| Apsis { position: parent_position - direction * distance, distance });
+        self.next_periapsis = elements.time_to_periapsis(mu);
+        self.next_apoapsis = elements.time_to_apoapsis(mu);
+        if let (Some(last), Some(mean_motion)) = (self.radial_velocity, elements.mean_motion(mu)) {
+            if last < 0.0 && radial_velocity >= 0.0 {
+                let passage = time - wrap_angle(elements.mean_anomaly) / mean_motion;
+                self.last_periapsis = Some(passage);
+                self.precession.record(direction, rotation * DVec3::Z, passage);
+            } else if last > 0.0 && radial_velocity <= 0.0 && elements.apoapsis_distance().is_some() {
+                self.last_apoapsis = Some(time - wrap_angle(elements.mean_anomaly - PI) / mean_motion);
+            }
+        }
+        self.radial_velocity = Some(radial_velocity);
+    }
+
+}
+
+//Into (-π, π], so a passage a moment ago gives a small positive angle
+fn wrap_angle(angle: f64) -> f64 {
+    PI - (PI - angle).rem_euclid(TAU)
+}
+
+//Tracks how far the periapsis turned between the first and the latest periapsis passage.
+//The turn is summed up passage by passage, so it keeps counting past half a revolution
+#[derive(Debug, Clone, Copy, Reflect, Default)]
+pub struct Precession {
+
+    pub passages: u32,
+    pub angle: f64, //in radians, positive in the direction of the orbit
+    last_direction: DVec3,
+    first_time: f64,
+    last_time: f64,
+
+}
+
+impl Precession {
+
+    pub fn arcseconds_per_century(&self) -> Option<f64> {
+        let elapsed = self.last_time - self.first_time;
+        if self.passages < 2 || elapsed <= 0.0 {
+            return None;
+        }
+        let century = DAY_IN_SECONDS as f64 * 365.25 * 100.0;
+        Some(self.angle.to_degrees() * 3600.0 * century / elapsed)
+    }
+
+    //The direction of the periapsis is taken from the osculating orbit, which doesn't depend on how close the sample is to the actual passage
+    fn record(&mut self, direction: DVec3, normal: DVec3, time: f64) {
+        if self.passages == 0 {
+            self.first_time = time;
+        } else {
+            self.angle += normal.dot(self.last_direction.cross(direction)).atan2(self.last_direction.dot(direction));
+        }
+        self.last_direction = direction;
+        self.last_time = time;
+        self.passages += 1;
+    }
 
 }
 
 #[derive(Debug, Clone, Copy, Reflect)]
 pub enum ApsisType {
-    Aphelion,
-    Perihelion
+    Apoapsis,
+    Periapsis
 }
 
+//Works for every body with a parent, so moons of moons get their apsides as well
 fn update_apsis(
-    stars: Query<(&SimPosition, &BodyChildren), (With<Star>, Without<Moon>, Without<Planet>)>,
-    mut planets: Query<(Entity, &SimPosition, &Transform, &mut ApsisBody, &BodyChildren), (With<Planet>, Without<Star>, Without<Moon>)>,
-    mut moons: Query<(Entity, &SimPosition, &Transform, &mut ApsisBody), (With<Moon>, Without<Star>, Without<Planet>)>,
+    mut bodies: Query<(&SimPosition, &Velocity, &Mass, &BodyParent, &mut ApsisBody)>,
+    parents: Query<(&SimPosition, &Velocity, &Mass)>,
+    sim_time: Res<SimTime>,
 ) {
-    for (entity, position, _, mut apsis, _) in &mut planets {
-        let mut parent = None;
-        for (s_pos, s_child) in &stars {
-            if s_child.0.contains(&entity) {
-                parent = Some(s_pos);
-                break;
-            }
-        }
-        if let Some(p_pos) = parent {
-            let new_distance = p_pos.current.distance(position.current) as f32;
-            //perihelion
-            if apsis.perihelion.distance > new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.perihelion.distance = new_distance;
-                apsis.perihelion.position = position.current;
-            }
-            if apsis.aphelion.distance < new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.aphelion.distance = new_distance;
-                apsis.aphelion.position = position.current;
-            }
-        }
+    for (position, velocity, mass, parent, mut apsis) in &mut bodies {
+        let Ok((p_pos, p_vel, p_mass)) = parents.get(parent.0) else {
+            continue;
+        };
+        let relative_position = position.current - p_pos.current;
+        let relative_velocity = velocity.0 - p_vel.0;
+        let mu = G * (p_mass.0 + mass.0);
+        let Some(elements) = OrbitalElements::from_state(relative_position, relative_velocity, mu) else {
+            continue;
+        };
+        apsis.update(&elements, p_pos.current, mu, relative_position.dot(relative_velocity), sim_time.0);
     }
-    for (entity, position, _, mut apsis) in &mut moons {
-        let mut parent = None;
-        for (_, s_pos, _, _, s_child) in &planets {
-            if s_child.0.contains(&entity) {
-                parent = Some(s_pos);
-                break;
-            }
-        }
-        if let Some(p_pos) = parent {
-            let new_distance = p_pos.current.distance(position.current) as f32;
-            //perihelion
-            if apsis.perihelion.distance > new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.perihelion.distance = new_distance;
-                apsis.perihelion.position = position.current;
-            }
-            if apsis.aphelion.distance < new_distance || apsis.perihelion.distance == 0.0 {
-                apsis.aphelion.distance = new_distance;
-                apsis.aphelion.position = position.current;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use bevy::math::DVec3;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use crate::constants::G;
+    use crate::simulation::components::orbital_elements::OrbitalElements;
+    use crate::simulation::integration::acceleration::calculate_accelerations;
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::ForceSettings;
+
+    use super::{ApsisBody, Precession};
+
+    fn offset(values: &[DVec3], derivatives: &[DVec3], timestep: f64) -> Vec<DVec3> {
+        values.iter().zip(derivatives).map(|(value, derivative)| *value + *derivative * timestep).collect()
+    }
+
+    //Sun and Mercury from the perihelion over 10 orbits in RK4 steps of about one hour, with the passages picked up by ApsisBody
+    //like in the simulation. The Newtonian run cancels the precession caused by the integrator
+    #[test]
+    fn mercury_perihelion_precession() {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let masses = [1.989e30, 3.301e23];
+        let mu = G * masses.iter().sum::<f64>();
+        let semi_major_axis = 5.7909e10;
+        let eccentricity = 0.2056;
+        let periapsis = semi_major_axis * (1.0 - eccentricity);
+        let periapsis_speed = (mu * (1.0 + eccentricity) / periapsis).sqrt();
+        let period = 2.0 * PI * (semi_major_axis.powi(3) / mu).sqrt();
+        let orbits = 10;
+        let steps_per_orbit = (period / 3600.0).round() as usize;
+        let timestep = period / steps_per_orbit as f64;
+
+        let precession = |relativity: bool| {
+            let forces = ForceSettings { relativity, ..Default::default() };
+            let perturbations = Perturbations::with_relativity(relativity);
+            let accelerations = |positions: &[DVec3], velocities: &[DVec3]| calculate_accelerations(positions, velocities, &masses, &forces, &perturbations);
+            let mercury_velocity = DVec3::new(0.0, periapsis_speed, 0.0);
+            let mut positions = vec![DVec3::ZERO, DVec3::new(periapsis, 0.0, 0.0)];
+            let mut velocities = vec![-mercury_velocity * masses[1] / masses[0], mercury_velocity];
+            let mut apsis = ApsisBody::default();
+            //half an orbit more, so the last passage is well inside the run
+            for step in 1..=orbits * steps_per_orbit + steps_per_orbit / 2 {
+                let k1_vel = velocities.clone();
+                let k1_acc = accelerations(&positions, &velocities);
+                let k2_vel = offset(&velocities, &k1_acc, timestep / 2.0);
+                let k2_acc = accelerations(&offset(&positions, &k1_vel, timestep / 2.0), &k2_vel);
+                let k3_vel = offset(&velocities, &k2_acc, timestep / 2.0);
+                let k3_acc = accelerations(&offset(&positions, &k2_vel, timestep / 2.0), &k3_vel);
+                let k4_vel = offset(&velocities, &k3_acc, timestep);
+                let k4_acc = accelerations(&offset(&positions, &k3_vel, timestep), &k4_vel);
+                for index in 0..positions.len() {
+                    positions[index] += timestep / 6.0 * (k1_vel[index] + 2.0 * k2_vel[index] + 2.0 * k3_vel[index] + k4_vel[index]);
+                    velocities[index] += timestep / 6.0 * (k1_acc[index] + 2.0 * k2_acc[index] + 2.0 * k3_acc[index] + k4_acc[index]);
+                }
+                let (position, velocity) = (positions[1] - positions[0], velocities[1] - velocities[0]);
+                let elements = OrbitalElements::from_state(position, velocity, mu).unwrap();
+                apsis.update(&elements, positions[0], mu, position.dot(velocity), step as f64 * timestep);
             }
+            assert_eq!(apsis.precession.passages, orbits as u32);
+            apsis.precession.arcseconds_per_century().unwrap()
+        };
+
+        let arcseconds_per_century = precession(true) - precession(false);
+        assert!((arcseconds_per_century - 43.0).abs() < 1.0, "perihelion precessed by {}″ per century", arcseconds_per_century);
+    }
+
+    //Apsides turning by 100° per passage, comparing only the first and the latest direction would wrap after two passages
+    #[test]
+    fn precession_past_half_a_turn() {
+        let mut precession = Precession::default();
+        for passage in 0..6 {
+            let angle = (passage as f64 * 100.0).to_radians();
+            precession.record(DVec3::new(angle.cos(), angle.sin(), 0.0), DVec3::Z, passage as f64 * 1000.0);
         }
+        assert!((precession.angle.to_degrees() - 500.0).abs() < 1e-9, "turned by {}°", precession.angle.to_degrees());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,81 @@
+use crate::simulation::components::body::{Atmosphere, BodyChildren, BodyParent, BodyRotation, BodyShape, Mass, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::collision::{absorb, detach_absorbed};
+use crate::simulation::components::selection::SelectedEntity;
+use crate::simulation::integration::acceleration::gravitational_mass;
+use crate::simulation::integration::{advance_sim_time, paused};
+use crate::simulation::ui::event_log::EventLog;
+use crate::simulation::ui::SimTime;
+use crate::utils::sim_state_type_simulation;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::{DMat3, DVec3};
+use bevy::prelude::{not, Commands, Entity, IntoScheduleConfigs, Name, Query, Res, ResMut, With};
+
+pub struct AtmospherePlugin;
+
+impl Plugin for AtmospherePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (check_reentry).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
+    }
+
+}
+
+//Semi axes of the BodyShape ellipsoid in meters, in the body fixed frame (the polar axis is z)
+pub fn surface_radii(shape: &BodyShape) -> DVec3 {
+    DVec3::new(
+        shape.ellipsoid.semi_major_equatorial_radius_km,
+        shape.ellipsoid.semi_minor_equatorial_radius_km,
+        shape.ellipsoid.polar_radius_km,
+    ) * 1000.0
+}
+
+//Height above the ellipsoid surface along the line to the center, negative below the surface.
+//to_body_frame is the transposed rotation matrix of the body
+pub fn altitude(relative: DVec3, to_body_frame: DMat3, radii: DVec3) -> f64 {
+    let distance = relative.length();
+    let scaled = (to_body_frame * relative / radii).length();
+    if scaled == 0.0 {
+        return -distance;
+    }
+    distance - distance / scaled
+}
+
+//A body that reaches the surface of its parent crashes into it, the parent takes its mass and momentum like in a merge
+fn check_reentry(
+    mut bodies: Query<(Entity, &Name, &mut Mass, &mut Velocity, &mut SimPosition)>,
+    atmospheres: Query<(&BodyShape, &BodyRotation), With<Atmosphere>>,
+    mut hierarchy: Query<(Option<&mut BodyParent>, Option<&mut BodyChildren>)>,
+    test_particles: Query<(), With<TestParticle>>,
+    sim_time: Res<SimTime>,
+    mut event_log: ResMut<EventLog>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut commands: Commands,
+) {
+    let mut crashed: Vec<(Entity, Entity)> = Vec::new();
+    for (entity, _, _, _, position) in &bodies {
+        let Ok((Some(parent), _)) = hierarchy.get(entity) else {
+            continue;
+        };
+        let (Ok((shape, rotation)), Ok((_, _, _, _, parent_position))) = (atmospheres.get(parent.0), bodies.get(parent.0)) else {
+            continue;
+        };
+        let to_body_frame = rotation.matrix.as_dmat3().transpose();
+        if altitude(position.current - parent_position.current, to_body_frame, surface_radii(shape)) <= 0.0 {
+            crashed.push((entity, parent.0));
+        }
+    }
+    for (entity, parent) in crashed {
+        let Ok([(_, parent_name, mut parent_mass, mut parent_velocity, mut parent_position), (_, name, mass, velocity, position)]) = bodies.get_many_mut([parent, entity]) else {
+            continue;
+        };
+        let absorbed_mass = gravitational_mass(entity, &mass, &test_particles);
+        absorb(&mut parent_mass, &mut parent_velocity, &mut parent_position, absorbed_mass, velocity.0, position.current);
+        event_log.add(sim_time.0, format!("{} re-entered the atmosphere of {} and reached the surface", name, parent_name));
+        detach_absorbed(entity, parent, &mut hierarchy, &mut commands);
+        if selected_entity.entity == Some(entity) {
+            selected_entity.change_entity(parent, false);
+        }
+        commands.entity(entity).despawn();
+    }
+}
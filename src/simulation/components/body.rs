@@ -63,6 +63,8 @@ pub struct OrbitSettings {
     pub arrow_scale: u64,
     pub auto_scale_arrows: bool,
     pub period: f64,
+    pub draw_orbit: bool, //conic section of the osculating orbit around the parent
+    pub draw_soi: bool, //Hill sphere and Laplace sphere of influence around the body
                          
 }
 
@@ -79,6 +81,8 @@ impl Default for OrbitSettings {
             draw_lines: false,
             step: 0.0,
             period: 0.0,
+            draw_orbit: false,
+            draw_soi: false,
             display_force: false,
             display_velocity: false,
             arrow_scale: 1,
@@ -94,13 +98,24 @@ impl Default for OrbitSettings {
 pub struct SimPosition {
 
     pub current: DVec3,
+    pub previous: DVec3, //position before the last fixed physics step, used for interpolating the rendered position
 
 }
 
 impl SimPosition {
 
     pub fn new(value: DVec3) -> Self {
-        SimPosition { current: value }
+        SimPosition { current: value, previous: value }
+    }
+
+    //moves the body without interpolating from the old position
+    pub fn set(&mut self, value: DVec3) {
+        self.current = value;
+        self.previous = value;
+    }
+
+    pub fn interpolated(&self, fraction: f64) -> DVec3 {
+        self.previous.lerp(self.current, fraction)
     }
 
 }
@@ -188,6 +203,96 @@ pub struct Planet;
 #[derive(Component, Reflect, Clone, Default)]
 pub struct Moon;
 
+//Zonal coefficients of the gravity field, the reference radius is the semi major equatorial radius of the BodyShape
+#[derive(Component, Reflect, Clone, Default, Copy, PartialEq)]
+pub struct ZonalHarmonics {
+
+    pub j2: f64,
+    pub j3: f64,
+    pub j4: f64,
+
+}
+
+impl ZonalHarmonics {
+
+    pub fn is_zero(&self) -> bool {
+        self.j2 == 0.0 && self.j3 == 0.0 && self.j4 == 0.0
+    }
+
+}
+
+//Surface that is pushed away from every enabled LightSource by its radiation
+#[derive(Component, Reflect, Clone, Copy, PartialEq)]
+pub struct RadiationPressure {
+
+    pub area: f64, //cross-sectional area in m²
+    pub reflectivity: f64, //1 absorbs all light, 2 reflects all of it back
+
+}
+
+impl Default for RadiationPressure {
+
+    fn default() -> Self {
+        RadiationPressure { area: 0.0, reflectivity: 1.0 }
+    }
+
+}
+
+impl RadiationPressure {
+
+    pub fn is_zero(&self) -> bool {
+        self.area == 0.0 || self.reflectivity == 0.0
+    }
+
+}
+
+//Exponential atmosphere that slows down the children of the body flying through it.
+//A single exponential only fits a band of altitudes, so the density is given at a reference altitude inside the band
+//the orbits fly through, e.g. 400 km for low Earth orbits, or at 0 for the surface
+#[derive(Component, Reflect, Clone, Copy, PartialEq, Default)]
+pub struct Atmosphere {
+
+    pub base_density: f64, //in kg/m³ at the reference altitude
+    pub reference_altitude: f64, //in meters
+    pub scale_height: f64, //in meters
+    pub top: f64, //altitude in meters, there is no drag above it
+
+}
+
+impl Atmosphere {
+
+    pub fn is_zero(&self) -> bool {
+        self.base_density == 0.0 || self.scale_height == 0.0 || self.top == 0.0
+    }
+
+    pub fn density(&self, altitude: f64) -> f64 {
+        if altitude > self.top {
+            return 0.0;
+        }
+        self.base_density * ((self.reference_altitude - altitude.max(0.0)) / self.scale_height).exp()
+    }
+
+}
+
+//Follows a fixed two body orbit around its parent instead of taking part in the N-body integration (simulate: false).
+//The state relative to the parent is taken from the body when the simulation starts and whenever it was edited
+#[derive(Component, Reflect, Clone, Copy, Default)]
+pub struct KeplerOrbit {
+
+    pub relative: Option<(DVec3, DVec3)>, //position and velocity
+    pub written: Option<(DVec3, DVec3)>, //absolute position and velocity set by the last step
+
+}
+
+//Position and velocity are taken from the loaded SPICE kernels every step instead of being integrated.
+//The body is still part of the integration, so it keeps attracting the other bodies
+#[derive(Component, Reflect, Clone, Default)]
+pub struct EphemerisDriven;
+
+//Feels the gravity of the other bodies but doesn't attract anything itself (spacecraft, debris, ...)
+#[derive(Component, Reflect, Clone, Default)]
+pub struct TestParticle;
+
 #[derive(Bundle, Clone, Default)]
 pub struct BodyBundle {
 
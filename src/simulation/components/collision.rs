@@ -0,0 +1,340 @@
+use crate::simulation::components::body::{BodyChildren, BodyParent, BodyShape, Mass, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::selection::SelectedEntity;
+use crate::simulation::integration::acceleration::gravitational_mass;
+use crate::simulation::integration::{advance_sim_time, paused, Pause, PhysicsStep, StepSpan};
+use crate::simulation::ui::event_log::EventLog;
+use crate::simulation::ui::toast::{important_error_toast, ToastContainer};
+use crate::simulation::ui::SimTime;
+use crate::utils::sim_state_type_simulation;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::DVec3;
+use bevy::prelude::{not, Commands, Entity, IntoScheduleConfigs, Mut, Name, Query, Res, ResMut, Resource, Transform, With};
+use serde::{Deserialize, Serialize};
+
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CollisionSettings>()
+            .add_systems(FixedUpdate, (handle_collisions).after(PhysicsStep).before(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
+    }
+
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionMode {
+    #[default]
+    Ignore,
+    Merge,
+    Bounce,
+    Pause
+}
+
+impl CollisionMode {
+
+    pub fn as_str(&self) -> String {
+        match self {
+            CollisionMode::Ignore => "Ignore".to_string(),
+            CollisionMode::Merge => "Merge (inelastic)".to_string(),
+            CollisionMode::Bounce => "Bounce (elastic)".to_string(),
+            CollisionMode::Pause => "Pause and notify".to_string()
+        }
+    }
+
+    pub fn all() -> Vec<CollisionMode> {
+        vec![CollisionMode::Ignore, CollisionMode::Merge, CollisionMode::Bounce, CollisionMode::Pause]
+    }
+
+}
+
+#[derive(Resource, Default, Clone)]
+pub struct CollisionSettings {
+
+    pub mode: CollisionMode, //set by the scenario
+    pub roche_breakup: bool, //bodies inside the Roche limit of their parent break up into fragments along their orbit
+
+}
+
+struct Collider {
+    entity: Entity,
+    start: DVec3, //position before the physics step
+    end: DVec3,
+    radius: f64,
+}
+
+impl Collider {
+
+    fn min_x(&self) -> f64 {
+        self.start.x.min(self.end.x) - self.radius
+    }
+
+    fn max_x(&self) -> f64 {
+        self.start.x.max(self.end.x) + self.radius
+    }
+
+}
+
+struct Contact {
+    first: Entity,
+    second: Entity,
+    time: f64, //fraction of the physics step at which the bodies touched
+    touching_before: bool,
+}
+
+fn handle_collisions(
+    mut bodies: Query<(Entity, &Name, &mut Mass, &mut Velocity, &mut SimPosition, &mut BodyShape, &mut Transform)>,
+    mut hierarchy: Query<(Option<&mut BodyParent>, Option<&mut BodyChildren>)>,
+    test_particles: Query<(), With<TestParticle>>,
+    settings: Res<CollisionSettings>,
+    span: Res<StepSpan>,
+    sim_time: Res<SimTime>,
+    mut pause: ResMut<Pause>,
+    mut toasts: ResMut<ToastContainer>,
+    mut event_log: ResMut<EventLog>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut commands: Commands,
+) {
+    if settings.mode == CollisionMode::Ignore {
+        return;
+    }
+    let span = span.0; //simulated seconds covered by this physics step
+    let mut removed: Vec<Entity> = Vec::new();
+    for contact in find_contacts(&bodies) {
+        if removed.contains(&contact.first) || removed.contains(&contact.second) {
+            continue;
+        }
+        let Ok([first, second]) = bodies.get_many_mut([contact.first, contact.second]) else {
+            continue;
+        };
+        let event_time = sim_time.0 + contact.time * span;
+        match settings.mode {
+            CollisionMode::Ignore => {}
+            CollisionMode::Pause => {
+                //bodies that already touched before the step were reported before
+                if contact.touching_before {
+                    continue;
+                }
+                let message = format!("{} collided with {}", first.1, second.1);
+                toasts.0.add(important_error_toast(format!("Simulation paused: {}", message).as_str()));
+                event_log.add(event_time, message);
+                pause.0 = true;
+                return;
+            }
+            CollisionMode::Merge => {
+                let (survivor, absorbed, message) = merge(first, second, &test_particles);
+                event_log.add(event_time, message);
+                detach_absorbed(absorbed, survivor, &mut hierarchy, &mut commands);
+                if selected_entity.entity == Some(absorbed) {
+                    selected_entity.change_entity(survivor, false);
+                }
+                commands.entity(absorbed).despawn();
+                removed.push(absorbed);
+            }
+            CollisionMode::Bounce => {
+                let message = format!("{} bounced off {}", first.1, second.1);
+                if bounce(first, second, contact.time, span, &test_particles) {
+                    event_log.add(event_time, message);
+                }
+            }
+        }
+    }
+}
+
+//Sweep and prune along the x axis, every body covers the whole path it moved along during the step
+//so fast bodies can't pass through each other between two steps
+fn find_contacts(
+    bodies: &Query<(Entity, &Name, &mut Mass, &mut Velocity, &mut SimPosition, &mut BodyShape, &mut Transform)>,
+) -> Vec<Contact> {
+    let mut colliders: Vec<Collider> = bodies.iter().map(|(entity, _, _, _, pos, shape, _)| Collider {
+        entity,
+        start: pos.previous,
+        end: pos.current,
+        radius: contact_radius(shape),
+    }).collect();
+    colliders.sort_by(|a, b| a.min_x().total_cmp(&b.min_x()));
+    let mut contacts = Vec::new();
+    for (index, first) in colliders.iter().enumerate() {
+        for second in &colliders[index + 1..] {
+            if second.min_x() > first.max_x() {
+                break;
+            }
+            if let Some((time, touching_before)) = contact_time(first, second) {
+                contacts.push(Contact { first: first.entity, second: second.entity, time, touching_before });
+            }
+        }
+    }
+    contacts.sort_by(|a, b| a.time.total_cmp(&b.time));
+    contacts
+}
+
+//The bodies collide as spheres around their largest semi-axis, so a flattened body is never passed through.
+//Near its poles the contact is found a bit early, by the difference of the equatorial and the polar radius at most
+fn contact_radius(shape: &BodyShape) -> f64 {
+    let ellipsoid = &shape.ellipsoid;
+    ellipsoid.semi_major_equatorial_radius_km.max(ellipsoid.semi_minor_equatorial_radius_km).max(ellipsoid.polar_radius_km) * 1000.0
+}
+
+//Both bodies move in a straight line during the step, so the distance between them is a quadratic in time
+fn contact_time(first: &Collider, second: &Collider) -> Option<(f64, bool)> {
+    let start = second.start - first.start;
+    let motion = (second.end - first.end) - start;
+    let reach = first.radius + second.radius;
+    let c = start.length_squared() - reach * reach;
+    if c <= 0.0 {
+        return Some((0.0, true));
+    }
+    let a = motion.length_squared();
+    let b = 2.0 * start.dot(motion);
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || b >= 0.0 || discriminant < 0.0 { //not getting closer or passing by
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    if time <= 1.0 {
+        Some((time, false))
+    } else {
+        None
+    }
+}
+
+//Perfectly inelastic merge: the heavier body absorbs the other one, momentum and the center of mass are conserved
+//and the volumes are added up. Test particles don't add their mass, they don't take part in the gravity either
+fn merge(
+    first: (Entity, &Name, Mut<Mass>, Mut<Velocity>, Mut<SimPosition>, Mut<BodyShape>, Mut<Transform>),
+    second: (Entity, &Name, Mut<Mass>, Mut<Velocity>, Mut<SimPosition>, Mut<BodyShape>, Mut<Transform>),
+    test_particles: &Query<(), With<TestParticle>>,
+) -> (Entity, Entity, String) {
+    let first_mass = gravitational_mass(first.0, &first.2, test_particles);
+    let second_mass = gravitational_mass(second.0, &second.2, test_particles);
+    let (survivor, absorbed, absorbed_mass) = if (second_mass, second.5.ellipsoid.mean_equatorial_radius_km()) > (first_mass, first.5.ellipsoid.mean_equatorial_radius_km()) {
+        (second, first, first_mass)
+    } else {
+        (first, second, second_mass)
+    };
+    let (survivor_entity, survivor_name, mut mass, mut velocity, mut position, mut shape, mut transform) = survivor;
+    let (absorbed_entity, absorbed_name, _, absorbed_velocity, absorbed_position, absorbed_shape, _) = absorbed;
+    absorb(&mut mass, &mut velocity, &mut position, absorbed_mass, absorbed_velocity.0, absorbed_position.current);
+    let radius = shape.ellipsoid.mean_equatorial_radius_km();
+    let absorbed_radius = absorbed_shape.ellipsoid.mean_equatorial_radius_km();
+    if radius > 0.0 {
+        let factor = (radius.powi(3) + absorbed_radius.powi(3)).cbrt() / radius;
+        shape.ellipsoid.semi_major_equatorial_radius_km *= factor;
+        shape.ellipsoid.semi_minor_equatorial_radius_km *= factor;
+        shape.ellipsoid.polar_radius_km *= factor;
+        transform.scale *= factor as f32;
+    }
+    (survivor_entity, absorbed_entity, format!("{} merged into {}", absorbed_name, survivor_name))
+}
+
+//Adds the mass and momentum of another body, the survivor moves to the common center of mass
+pub fn absorb(
+    mass: &mut Mass,
+    velocity: &mut Velocity,
+    position: &mut SimPosition,
+    absorbed_mass: f64,
+    absorbed_velocity: DVec3,
+    absorbed_position: DVec3,
+) {
+    let total_mass = mass.0 + absorbed_mass;
+    if absorbed_mass > 0.0 && total_mass > 0.0 {
+        velocity.0 = (velocity.0 * mass.0 + absorbed_velocity * absorbed_mass) / total_mass;
+        position.set((position.current * mass.0 + absorbed_position * absorbed_mass) / total_mass);
+    }
+    mass.0 = total_mass;
+}
+
+//The absorbed body disappears from the body tree, its children now orbit the survivor
+pub fn detach_absorbed(
+    absorbed: Entity,
+    survivor: Entity,
+    hierarchy: &mut Query<(Option<&mut BodyParent>, Option<&mut BodyChildren>)>,
+    commands: &mut Commands,
+) {
+    let (absorbed_parent, absorbed_children) = match hierarchy.get(absorbed) {
+        Ok((parent, children)) => (parent.map(|p| p.0), children.map(|c| c.0.clone()).unwrap_or_default()),
+        Err(_) => return
+    };
+    if let Some(parent) = absorbed_parent {
+        if let Ok((_, Some(mut children))) = hierarchy.get_mut(parent) {
+            children.0.retain(|child| *child != absorbed);
+        }
+    }
+    let survivor_parent = hierarchy.get(survivor).ok().and_then(|(parent, _)| parent.map(|p| p.0));
+    if survivor_parent == Some(absorbed) {
+        //the survivor takes the place of its former parent
+        match absorbed_parent {
+            Some(parent) => {
+                if let Ok((Some(mut survivor_parent), _)) = hierarchy.get_mut(survivor) {
+                    survivor_parent.0 = parent;
+                }
+                if let Ok((_, Some(mut children))) = hierarchy.get_mut(parent) {
+                    children.0.push(survivor);
+                }
+            }
+            None => {
+                commands.entity(survivor).remove::<BodyParent>();
+            }
+        }
+    }
+    let moved: Vec<Entity> = absorbed_children.into_iter().filter(|child| *child != survivor).collect();
+    if moved.is_empty() {
+        return;
+    }
+    for child in &moved {
+        if let Ok((Some(mut parent), _)) = hierarchy.get_mut(*child) {
+            parent.0 = survivor;
+        }
+    }
+    match hierarchy.get_mut(survivor) {
+        Ok((_, Some(mut children))) => children.0.extend(moved),
+        _ => {
+            commands.entity(survivor).insert(BodyChildren(moved));
+        }
+    }
+}
+
+//Elastic bounce along the line between the centers at the moment of contact,
+//the rest of the step is then covered with the new velocities. Returns false if the bodies were already separating
+fn bounce(
+    first: (Entity, &Name, Mut<Mass>, Mut<Velocity>, Mut<SimPosition>, Mut<BodyShape>, Mut<Transform>),
+    second: (Entity, &Name, Mut<Mass>, Mut<Velocity>, Mut<SimPosition>, Mut<BodyShape>, Mut<Transform>),
+    time: f64,
+    span: f64,
+    test_particles: &Query<(), With<TestParticle>>,
+) -> bool {
+    let (first_entity, _, first_mass, mut first_velocity, mut first_position, first_shape, _) = first;
+    let (second_entity, _, second_mass, mut second_velocity, mut second_position, second_shape, _) = second;
+    let first_mass = gravitational_mass(first_entity, &first_mass, test_particles);
+    let second_mass = gravitational_mass(second_entity, &second_mass, test_particles);
+    let mut first_contact = first_position.previous.lerp(first_position.current, time);
+    let mut second_contact = second_position.previous.lerp(second_position.current, time);
+    let normal = (second_contact - first_contact).normalize_or_zero();
+    if normal == DVec3::ZERO {
+        return false;
+    }
+    //share of the impulse each body takes, massless bodies and test particles bounce off without pushing the other one
+    let total_mass = first_mass + second_mass;
+    let (first_share, second_share) = if total_mass > 0.0 {
+        (second_mass / total_mass, first_mass / total_mass)
+    } else {
+        (0.5, 0.5)
+    };
+    let approach = (second_velocity.0 - first_velocity.0).dot(normal);
+    let bounced = approach < 0.0;
+    if bounced {
+        first_velocity.0 += normal * (2.0 * approach * first_share);
+        second_velocity.0 -= normal * (2.0 * approach * second_share);
+    }
+    let reach = contact_radius(&first_shape) + contact_radius(&second_shape);
+    let overlap = reach - first_contact.distance(second_contact);
+    if overlap > 0.0 {
+        first_contact -= normal * (overlap * first_share);
+        second_contact += normal * (overlap * second_share);
+    }
+    let remaining = (1.0 - time) * span;
+    first_position.current = first_contact + first_velocity.0 * remaining;
+    second_position.current = second_contact + second_velocity.0 * remaining;
+    bounced
+}
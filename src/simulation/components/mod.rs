@@ -1,15 +1,20 @@
 use crate::simulation::components::anise::AnisePlugin;
 use crate::simulation::components::apsis::ApsisPlugin;
+use crate::simulation::components::atmosphere::AtmospherePlugin;
 use crate::simulation::components::billboard::BodyBillboardPlugin;
+use crate::simulation::components::collision::CollisionPlugin;
 use crate::simulation::components::direction::DirectionPlugin;
 use crate::simulation::components::lock_on::LockOnPlugin;
 use crate::simulation::components::motion_line::MotionLinePlugin;
+use crate::simulation::components::orbital_elements::OrbitalElementsPlugin;
 use crate::simulation::components::reset::ResetPlugin;
+use crate::simulation::components::roche::RochePlugin;
 use crate::simulation::components::rotation::RotationPlugin;
 use crate::simulation::components::scale::ScalePlugin;
 use crate::simulation::components::selection::SelectionPlugin;
 use crate::simulation::components::shape::DiameterPlugin;
 use crate::simulation::components::speed::SpeedPlugin;
+use crate::simulation::components::sphere_of_influence::SphereOfInfluencePlugin;
 use crate::simulation::integration::IntegrationPlugin;
 use bevy::app::Plugin;
 
@@ -29,6 +34,11 @@ pub mod editor;
 pub mod horizons;
 pub mod scale;
 pub mod anise;
+pub mod collision;
+pub mod atmosphere;
+pub mod roche;
+pub mod orbital_elements;
+pub mod sphere_of_influence;
 mod spacecraft;
 
 pub struct SimComponentPlugin;
@@ -38,7 +48,9 @@ impl Plugin for SimComponentPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
             .add_plugins(ApsisPlugin)
+            .add_plugins(AtmospherePlugin)
             .add_plugins(BodyBillboardPlugin)
+            .add_plugins(CollisionPlugin)
           //  .add_plugins(PanOrbitCameraPlugin)
             .add_plugins(DiameterPlugin)
             .add_plugins(DirectionPlugin)
@@ -46,10 +58,13 @@ impl Plugin for SimComponentPlugin {
             .add_plugins(LockOnPlugin)
             .add_plugins(ScalePlugin)
             .add_plugins(MotionLinePlugin)
+            .add_plugins(OrbitalElementsPlugin)
             .add_plugins(ResetPlugin)
+            .add_plugins(RochePlugin)
             .add_plugins(RotationPlugin)
             .add_plugins(SelectionPlugin)
             .add_plugins(SpeedPlugin)
+            .add_plugins(SphereOfInfluencePlugin)
             .add_plugins(AnisePlugin);
     }
 
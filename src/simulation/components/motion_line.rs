@@ -1,9 +1,14 @@
-use crate::simulation::components::body::{BillboardVisible, BodyChildren, BodyShape, Moon, OrbitSettings, Planet, SimPosition, Star};
+use std::f64::consts::PI;
+
+use crate::constants::G;
+use crate::simulation::components::body::{BillboardVisible, BodyChildren, BodyParent, BodyShape, Mass, Moon, OrbitSettings, Planet, SimPosition, Star, Velocity};
+use crate::simulation::components::orbital_elements::OrbitalElements;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::speed::Speed;
 use crate::simulation::integration::{paused, Pause, SimulationStep, SubSteps};
 use crate::utils::sim_state_type_simulation;
-use bevy::prelude::not;
+use bevy::math::{DVec3, Isometry3d};
+use bevy::prelude::{not, Update};
 use bevy::{prelude::{App, Entity, Gizmos, IntoScheduleConfigs, Plugin, PreUpdate, Query, Res, Resource, Transform, Vec3, With, Without}, time::Time};
 
 pub struct MotionLinePlugin;
@@ -12,7 +17,8 @@ impl Plugin for MotionLinePlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<OrbitOffset>()
-            .add_systems(PreUpdate, (update_lines.after(SimulationStep).run_if(not(paused)), (draw_orbit_line).after(update_lines)).run_if(sim_state_type_simulation));
+            .add_systems(PreUpdate, (update_lines.after(SimulationStep).run_if(not(paused)), (draw_orbit_line).after(update_lines)).run_if(sim_state_type_simulation))
+            .add_systems(Update, (draw_orbit_conic.after(SimulationStep)).run_if(sim_state_type_simulation));
     }
 }
 
@@ -34,6 +40,8 @@ impl Default for OrbitOffset {
 }
 
 const MULTIPLIER: f32 = 0.0001;
+const CONIC_SEGMENTS: usize = 256;
+const APSIS_MARKER_SIZE: f32 = 0.02; //relative to the periapsis distance
 //const PLANET_HIDE_MULTIPLIER: f32 = 10000.0;
 //const HIDE_MULTIPLIER: f32 = 100.0;
 
@@ -109,42 +117,52 @@ fn draw_orbit_line(
     }
 }
 
-/*fn draw_orbit_line_ellipse(
-    offset: Res<OrbitOffset>,
+//Draws the conic section of the osculating orbit around the parent. It is computed from the current state every frame,
+//so unlike the motion lines it follows edits of the mass or velocity right away
+fn draw_orbit_conic(
+    bodies: Query<(&SimPosition, &Velocity, &Mass, &BodyParent, &OrbitSettings)>,
+    parents: Query<(&SimPosition, &Velocity, &Mass, &Transform)>,
     mut gizmos: Gizmos,
-    bodies: Query<(Option<&ApsisBody>, Option<&BodyParent>, Option<&OrbitSettings>,&Velocity, &Transform)>,
-    simulation_scale: Res<SimulationScale>
+    scale: Res<SimulationScale>
 ) {
-    for (apsis, parent, orbit, vel, transform) in &bodies {
-        if parent.is_none() {
+    for (position, velocity, mass, parent, orbit) in &bodies {
+        if !orbit.draw_orbit {
             continue;
         }
+        let Ok((p_pos, p_vel, p_mass, p_transform)) = parents.get(parent.0) else {
+            continue;
+        };
+        let relative = position.current - p_pos.current;
+        let Some(elements) = OrbitalElements::from_state(relative, velocity.0 - p_vel.0, G * (mass.0 + p_mass.0)) else {
+            continue;
+        };
+        let semi_latus_rectum = elements.semi_major_axis * (1.0 - elements.eccentricity * elements.eccentricity);
+        if !semi_latus_rectum.is_finite() || semi_latus_rectum <= 0.0 {
+            continue;
+        }
+        let rotation = elements.perifocal_rotation();
+        let to_render = |true_anomaly: f64| {
+            let radius = semi_latus_rectum / (1.0 + elements.eccentricity * true_anomaly.cos());
+            let point = rotation * DVec3::new(true_anomaly.cos(), true_anomaly.sin(), 0.0) * radius;
+            p_transform.translation + scale.m_to_unit_dvec(point).as_vec3()
+        };
+        //hyperbolic arcs are cut off where they get twice as far away as the body is now
+        let max_anomaly = if elements.eccentricity < 1.0 {
+            PI
+        } else {
+            let max_radius = f64::max(2.0 * relative.length(), 4.0 * semi_latus_rectum / (1.0 + elements.eccentricity));
+            ((semi_latus_rectum / max_radius - 1.0) / elements.eccentricity).clamp(-1.0, 1.0).acos()
+        };
+        gizmos.linestrip((0..=CONIC_SEGMENTS).map(|index| to_render(-max_anomaly + 2.0 * max_anomaly * index as f64 / CONIC_SEGMENTS as f64)), orbit.color);
 
-        let parent_pos = bodies.get(parent.unwrap().0).unwrap().4.translation;
-        let apsis = apsis.unwrap();
-
-        let ecc = (apsis.aphelion.distance - apsis.perihelion.distance) / (apsis.aphelion.distance + apsis.perihelion.distance);
-        let semi_major = (apsis.aphelion.distance + apsis.perihelion.distance) / 2.0;
-        let semi_minor = semi_major * (1.0 - ecc.powi(2)).sqrt();
-        let half_size = simulation_scale.0 * Vec2::new(semi_major, semi_minor);
-
-        // 1. Calculate the orbital angular momentum vector
-        let relative_position = transform.translation - parent_pos;
-        let relative_velocity = vel.0.as_vec3();
-        let angular_momentum = relative_position.cross(relative_velocity);
-
-        // 2. Inclination (angle between angular momentum vector and z-axis)
-        let h_z = angular_momentum.z;
-        let h_magnitude = angular_momentum.length();
-        let inclination = (h_z / h_magnitude).acos();
-
-        // 3. Apply only the inclination rotation (ignore periapsis for now)
-        let inclination_rotation = Quat::from_rotation_x(inclination);
-        let pos = (apsis.perihelion.position + apsis.aphelion.position) / 2.0;
-        // 4. Draw the ellipse with only inclination applied
-        gizmos.ellipse(pos.as_vec3() * simulation_scale.0 + offset.value, inclination_rotation, half_size, orbit.unwrap().color).resolution(64);
+        let periapsis = to_render(0.0);
+        let marker_radius = p_transform.translation.distance(periapsis) * APSIS_MARKER_SIZE;
+        gizmos.sphere(Isometry3d::from_translation(periapsis), marker_radius, orbit.color);
+        if elements.eccentricity < 1.0 {
+            gizmos.sphere(Isometry3d::from_translation(to_render(PI)), marker_radius, orbit.color);
+        }
     }
-}*/
+}
 
 pub fn draw_lines(orbit: &OrbitSettings, offset: Vec3, gizmos: &mut Gizmos, current_pos: Vec3) {
     for (index, first) in orbit.lines.iter().enumerate() {
@@ -0,0 +1,249 @@
+use std::f64::consts::{PI, TAU};
+
+use crate::constants::G;
+use crate::simulation::components::body::{BodyParent, Mass, SimPosition, Velocity};
+use crate::simulation::integration::SimulationStep;
+use crate::utils::sim_state_type_simulation;
+use bevy::math::{DQuat, DVec3};
+use bevy::prelude::{App, Commands, Component, Entity, IntoScheduleConfigs, Plugin, Query, Reflect, Update};
+
+const EPSILON: f64 = 1e-10; //below this an orbit counts as circular or equatorial
+const MAX_ITERATIONS: usize = 50;
+
+pub struct OrbitalElementsPlugin;
+
+impl Plugin for OrbitalElementsPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .register_type::<OrbitalElements>()
+            .add_systems(Update, (update_orbital_elements.after(SimulationStep)).run_if(sim_state_type_simulation));
+    }
+
+}
+
+//Osculating Keplerian elements of the orbit around the BodyParent, updated every frame.
+//The angles are in radians and relative to the xy plane and x axis of the simulation frame (J2000 equator)
+#[derive(Component, Debug, Clone, Copy, Reflect, Default)]
+pub struct OrbitalElements {
+
+    pub semi_major_axis: f64, //in m, negative for hyperbolic orbits and infinite for parabolic ones
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub ascending_node: f64, //longitude of the ascending node (RAAN)
+    pub argument_of_periapsis: f64,
+    pub true_anomaly: f64,
+    pub mean_anomaly: f64,
+    pub period: Option<f64>, //in seconds, only for bound orbits
+
+}
+
+impl OrbitalElements {
+
+    //Elements of the relative position and velocity with mu = G * (M + m).
+    //Circular orbits measure from the ascending node, equatorial ones from the x axis
+    pub fn from_state(position: DVec3, velocity: DVec3, mu: f64) -> Option<Self> {
+        let r = position.length();
+        let angular_momentum = position.cross(velocity);
+        let h = angular_momentum.length();
+        if mu <= 0.0 || r == 0.0 || h == 0.0 {
+            return None;
+        }
+        let normal = angular_momentum / h;
+        let node = DVec3::Z.cross(angular_momentum);
+        let eccentricity_vector = (position * (velocity.length_squared() - mu / r) - velocity * position.dot(velocity)) / mu;
+        let eccentricity = eccentricity_vector.length();
+        let energy = velocity.length_squared() / 2.0 - mu / r;
+        let semi_major_axis = if energy == 0.0 { f64::INFINITY } else { -mu / (2.0 * energy) };
+
+        let node_direction = if node.length() > EPSILON * h { node.normalize() } else { DVec3::X };
+        let periapsis_direction = if eccentricity > EPSILON { eccentricity_vector / eccentricity } else { node_direction };
+        let true_anomaly = angle_about(periapsis_direction, position, normal);
+        Some(OrbitalElements {
+            semi_major_axis,
+            eccentricity,
+            inclination: normal.z.clamp(-1.0, 1.0).acos(),
+            ascending_node: node_direction.y.atan2(node_direction.x).rem_euclid(TAU),
+            argument_of_periapsis: angle_about(node_direction, periapsis_direction, normal),
+            true_anomaly,
+            mean_anomaly: mean_anomaly(true_anomaly, eccentricity),
+            period: (semi_major_axis > 0.0 && eccentricity < 1.0).then(|| TAU * (semi_major_axis.powi(3) / mu).sqrt()),
+        })
+    }
+
+    //Relative position and velocity at the true anomaly, the inverse of from_state.
+    //Parabolic orbits and points beyond the asymptotes of a hyperbola have no state
+    pub fn to_state(&self, mu: f64) -> Option<(DVec3, DVec3)> {
+        let semi_latus_rectum = self.semi_major_axis * (1.0 - self.eccentricity * self.eccentricity);
+        if mu <= 0.0 || !semi_latus_rectum.is_finite() || semi_latus_rectum <= 0.0 {
+            return None;
+        }
+        let (sin, cos) = self.true_anomaly.sin_cos();
+        let denominator = 1.0 + self.eccentricity * cos;
+        if denominator <= 0.0 {
+            return None;
+        }
+        let rotation = self.perifocal_rotation();
+        let position = rotation * DVec3::new(cos, sin, 0.0) * (semi_latus_rectum / denominator);
+        let velocity = rotation * DVec3::new(-sin, self.eccentricity + cos, 0.0) * (mu / semi_latus_rectum).sqrt();
+        Some((position, velocity))
+    }
+
+    //Distance of the periapsis from the parent in m, not defined for parabolic orbits
+    pub fn periapsis_distance(&self) -> Option<f64> {
+        let distance = self.semi_major_axis * (1.0 - self.eccentricity);
+        (distance.is_finite() && distance > 0.0).then_some(distance)
+    }
+
+    //Only bound orbits reach an apoapsis
+    pub fn apoapsis_distance(&self) -> Option<f64> {
+        (self.semi_major_axis > 0.0 && self.eccentricity < 1.0).then(|| self.semi_major_axis * (1.0 + self.eccentricity))
+    }
+
+    //Change of the mean anomaly in radians per second
+    pub fn mean_motion(&self, mu: f64) -> Option<f64> {
+        let a = self.semi_major_axis.abs();
+        (mu > 0.0 && a.is_finite() && a > 0.0).then(|| (mu / a.powi(3)).sqrt())
+    }
+
+    //Seconds until the body passes the periapsis, hyperbolic orbits only pass it once
+    pub fn time_to_periapsis(&self, mu: f64) -> Option<f64> {
+        let mean_motion = self.mean_motion(mu)?;
+        if self.eccentricity < 1.0 {
+            Some((TAU - self.mean_anomaly).rem_euclid(TAU) / mean_motion)
+        } else {
+            (self.mean_anomaly < 0.0).then(|| -self.mean_anomaly / mean_motion)
+        }
+    }
+
+    pub fn time_to_apoapsis(&self, mu: f64) -> Option<f64> {
+        self.apoapsis_distance()?;
+        Some((PI - self.mean_anomaly).rem_euclid(TAU) / self.mean_motion(mu)?)
+    }
+
+    //Turns the perifocal frame (x towards the periapsis, z along the orbit normal) into the simulation frame
+    pub fn perifocal_rotation(&self) -> DQuat {
+        DQuat::from_rotation_z(self.ascending_node) * DQuat::from_rotation_x(self.inclination) * DQuat::from_rotation_z(self.argument_of_periapsis)
+    }
+
+}
+
+//Solves Kepler's equation for the eccentric (or hyperbolic) anomaly with Newton's method
+pub fn true_anomaly_from_mean(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    if eccentricity < 1.0 {
+        let mean_anomaly = mean_anomaly.rem_euclid(TAU);
+        let mut eccentric_anomaly = if eccentricity > 0.8 { PI } else { mean_anomaly };
+        for _ in 0..MAX_ITERATIONS {
+            let step = (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly) / (1.0 - eccentricity * eccentric_anomaly.cos());
+            eccentric_anomaly -= step;
+            if step.abs() < EPSILON {
+                break;
+            }
+        }
+        (2.0 * (((1.0 + eccentricity) / (1.0 - eccentricity)).sqrt() * (eccentric_anomaly / 2.0).tan()).atan()).rem_euclid(TAU)
+    } else if eccentricity > 1.0 {
+        let mut hyperbolic_anomaly = (mean_anomaly / eccentricity).asinh();
+        for _ in 0..MAX_ITERATIONS {
+            let step = (eccentricity * hyperbolic_anomaly.sinh() - hyperbolic_anomaly - mean_anomaly) / (eccentricity * hyperbolic_anomaly.cosh() - 1.0);
+            hyperbolic_anomaly -= step;
+            if step.abs() < EPSILON {
+                break;
+            }
+        }
+        2.0 * (((eccentricity + 1.0) / (eccentricity - 1.0)).sqrt() * (hyperbolic_anomaly / 2.0).tanh()).atan()
+    } else {
+        //Barker's equation, mean_anomaly = D + D³/3 with D = tan(ν/2)
+        let root = (1.5 * mean_anomaly + (2.25 * mean_anomaly * mean_anomaly + 1.0).sqrt()).cbrt();
+        2.0 * (root - 1.0 / root).atan()
+    }
+
+}
+
+//Angle from one direction to another, counterclockwise around the axis, in [0, 2π)
+fn angle_about(from: DVec3, to: DVec3, axis: DVec3) -> f64 {
+    axis.dot(from.cross(to)).atan2(from.dot(to)).rem_euclid(TAU)
+}
+
+fn mean_anomaly(true_anomaly: f64, eccentricity: f64) -> f64 {
+    let half = (true_anomaly / 2.0).tan();
+    if eccentricity < 1.0 {
+        let eccentric_anomaly = 2.0 * (((1.0 - eccentricity) / (1.0 + eccentricity)).sqrt() * half).atan();
+        (eccentric_anomaly - eccentricity * eccentric_anomaly.sin()).rem_euclid(TAU)
+    } else if eccentricity > 1.0 {
+        //true anomalies outside the asymptotes can't be reached on a hyperbola
+        let ratio = (((eccentricity - 1.0) / (eccentricity + 1.0)).sqrt() * half).clamp(-1.0 + f64::EPSILON, 1.0 - f64::EPSILON);
+        let hyperbolic_anomaly = 2.0 * ratio.atanh();
+        eccentricity * hyperbolic_anomaly.sinh() - hyperbolic_anomaly
+    } else {
+        half + half.powi(3) / 3.0
+    }
+}
+
+fn update_orbital_elements(
+    mut bodies: Query<(Entity, &SimPosition, &Velocity, &Mass, &BodyParent, Option<&mut OrbitalElements>)>,
+    parents: Query<(&SimPosition, &Velocity, &Mass)>,
+    mut commands: Commands,
+) {
+    for (entity, position, velocity, mass, parent, elements) in &mut bodies {
+        let Ok((parent_position, parent_velocity, parent_mass)) = parents.get(parent.0) else {
+            continue;
+        };
+        let Some(new_elements) = OrbitalElements::from_state(position.current - parent_position.current, velocity.0 - parent_velocity.0, G * (parent_mass.0 + mass.0)) else {
+            continue;
+        };
+        match elements {
+            Some(mut elements) => *elements = new_elements,
+            None => {
+                commands.entity(entity).insert(new_elements);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use bevy::math::DVec3;
+
+    use super::OrbitalElements;
+
+    const MU: f64 = 3.986004418e14; //Earth
+
+    fn angle_difference(a: f64, b: f64) -> f64 {
+        let difference = (a - b).rem_euclid(TAU);
+        difference.min(TAU - difference)
+    }
+
+    //An inclined ellipse and a hyperbola before and after the periapsis, elements to vectors and back
+    #[test]
+    fn elements_round_trip() {
+        let orbits = [
+            (7.0e6, 0.1, 0.9, 2.0),
+            (2.4e7, 0.7, 0.3, 4.5),
+            (-1.5e7, 1.8, 0.5, -1.0),
+            (-1.5e7, 1.8, 0.5, 1.2),
+        ];
+        for (semi_major_axis, eccentricity, inclination, true_anomaly) in orbits {
+            let elements = OrbitalElements {
+                semi_major_axis,
+                eccentricity,
+                inclination,
+                ascending_node: 1.1,
+                argument_of_periapsis: 0.4,
+                true_anomaly,
+                ..Default::default()
+            };
+            let (position, velocity) = elements.to_state(MU).unwrap();
+            let result = OrbitalElements::from_state(position, velocity, MU).unwrap();
+            assert!((result.semi_major_axis / semi_major_axis - 1.0).abs() < 1e-9, "semi-major axis {} instead of {}", result.semi_major_axis, semi_major_axis);
+            assert!((result.eccentricity - eccentricity).abs() < 1e-9, "eccentricity {} instead of {}", result.eccentricity, eccentricity);
+            for (angle, expected) in [(result.inclination, inclination), (result.ascending_node, 1.1), (result.argument_of_periapsis, 0.4), (result.true_anomaly, true_anomaly)] {
+                assert!(angle_difference(angle, expected) < 1e-9, "angle {} instead of {}", angle, expected);
+            }
+            let (new_position, new_velocity): (DVec3, DVec3) = result.to_state(MU).unwrap();
+            assert!((new_position - position).length() / position.length() < 1e-9);
+            assert!((new_velocity - velocity).length() / velocity.length() < 1e-9);
+        }
+    }
+}
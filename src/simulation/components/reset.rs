@@ -3,7 +3,8 @@ use crate::simulation::components::anise::AlmanacHolder;
 use crate::simulation::components::body::Mass;
 use crate::simulation::components::selection::SelectedEntity;
 use crate::simulation::components::speed::Speed;
-use crate::simulation::integration::{Pause, SubSteps};
+use crate::simulation::integration::conservation::Conservation;
+use crate::simulation::integration::{AdaptiveStepStats, Pause, SubSteps};
 use crate::simulation::scenario::loading::LoadingState;
 use crate::simulation::scenario::setup::ScenarioData;
 use crate::simulation::ui::scenario_selection::SelectedScenario;
@@ -39,7 +40,9 @@ fn clean_up(
     mut camera: Query<&mut PanOrbitCamera>,
     mut ui_state: ResMut<UiState>,
     scenario_data: Res<ScenarioData>,
-    mut almanac_holder: ResMut<AlmanacHolder>
+    mut almanac_holder: ResMut<AlmanacHolder>,
+    mut adaptive_stats: ResMut<AdaptiveStepStats>,
+    mut conservation: ResMut<Conservation>
 ) {
     for entity in m_entities.iter() {
         commands.entity(entity).despawn()
@@ -50,6 +53,8 @@ fn clean_up(
     sim_time.0 = 0.0;
     selected_entity.entity = None;
     sub_steps.0 = DEFAULT_SUB_STEPS;
+    *adaptive_stats = AdaptiveStepStats::default();
+    *conservation = Conservation::default();
     scenario.spawned = false;
     loading_state.reset();
     let mut cam = camera.single_mut().unwrap();
@@ -57,6 +62,8 @@ fn clean_up(
     ui_state.visible = true;
     ui_state.step_type = StepType::SUBSTEPS;
     ui_state.show_debug = false;
+    ui_state.show_events = false;
+    ui_state.show_divergence = false;
 }
 
 fn switch_to_menu(
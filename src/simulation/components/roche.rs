@@ -0,0 +1,185 @@
+use std::f64::consts::PI;
+
+use crate::simulation::components::apsis::ApsisBody;
+use crate::simulation::components::body::{BodyBundle, BodyChildren, BodyParent, BodyRotation, BodyShape, Mass, ModelPath, Moon, OrbitSettings, Planet, SceneHandle, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::collision::{detach_absorbed, CollisionSettings};
+use crate::simulation::components::selection::SelectedEntity;
+use crate::simulation::integration::{advance_sim_time, paused};
+use crate::simulation::ui::event_log::EventLog;
+use crate::simulation::ui::toast::{warning_toast, ToastContainer};
+use crate::simulation::ui::SimTime;
+use crate::simulation::units::text_formatter::format_length;
+use crate::utils::sim_state_type_simulation;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::{DQuat, DVec3};
+use bevy::pbr::{MeshMaterial3d, StandardMaterial};
+use bevy::prelude::{default, not, Commands, Component, Entity, GlobalTransform, Handle, Has, IntoScheduleConfigs, Mesh, Mesh3d, Name, Query, Res, ResMut, Transform, Visibility, Without};
+use bevy::scene::{SceneInstance, SceneSpawner};
+
+const FLUID_ROCHE_FACTOR: f64 = 2.44; //d = 2.44 * R * (rho_parent / rho)^(1/3) for a body held together only by its own gravity
+const FRAGMENTS: usize = 32;
+const FRAGMENT_SPACING: f64 = 4.0; //distance between neighbouring fragments along the orbit, in fragment radii
+
+pub struct RochePlugin;
+
+impl Plugin for RochePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (check_roche_limit).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
+    }
+
+}
+
+//Whether the body was inside the Roche limit of its parent at the last check, so only crossings are reported
+#[derive(Component)]
+pub struct RocheState {
+
+    pub inside: bool,
+
+}
+
+//Distance to the parent in meters below which the body is torn apart by tides.
+//The density of the body follows from its mass and the volume of its BodyShape ellipsoid
+pub fn roche_limit(parent_mass: f64, mass: f64, shape: &BodyShape) -> Option<f64> {
+    let ellipsoid = &shape.ellipsoid;
+    let volume = 4.0 / 3.0 * PI * ellipsoid.semi_major_equatorial_radius_km * ellipsoid.semi_minor_equatorial_radius_km * ellipsoid.polar_radius_km * 1e9;
+    if volume <= 0.0 || mass <= 0.0 || parent_mass <= 0.0 {
+        return None;
+    }
+    let density = mass / volume;
+    //R * rho_parent^(1/3) only depends on the mass of the parent
+    Some(FLUID_ROCHE_FACTOR * (3.0 * parent_mass / (4.0 * PI * density)).cbrt())
+}
+
+fn check_roche_limit(
+    bodies: Query<(Entity, &Name, &SimPosition, &Velocity, &Mass, &BodyShape, &BodyRotation, &Transform, &SceneHandle, &ModelPath, &OrbitSettings, Option<&RocheState>), Without<TestParticle>>,
+    markers: Query<(Has<Planet>, Has<Moon>)>,
+    parents: Query<(&Name, &SimPosition, &Velocity, &Mass)>,
+    scenes: Query<&SceneInstance>,
+    scene_meshes: Query<(&Mesh3d, &MeshMaterial3d<StandardMaterial>, &GlobalTransform)>,
+    global_transforms: Query<&GlobalTransform>,
+    spawner: Res<SceneSpawner>,
+    mut hierarchy: Query<(Option<&mut BodyParent>, Option<&mut BodyChildren>)>,
+    settings: Res<CollisionSettings>,
+    sim_time: Res<SimTime>,
+    mut toasts: ResMut<ToastContainer>,
+    mut event_log: ResMut<EventLog>,
+    mut selected_entity: ResMut<SelectedEntity>,
+    mut commands: Commands,
+) {
+    let mut broken_up: Vec<(Entity, Entity, Vec<Entity>)> = Vec::new();
+    for (entity, name, position, velocity, mass, shape, rotation, transform, scene, model_path, orbit, state) in &bodies {
+        let Ok((Some(parent), _)) = hierarchy.get(entity) else {
+            continue;
+        };
+        let parent = parent.0;
+        let Ok((parent_name, parent_position, parent_velocity, parent_mass)) = parents.get(parent) else {
+            continue;
+        };
+        let Some(limit) = roche_limit(parent_mass.0, mass.0, shape) else {
+            continue;
+        };
+        let inside = position.current.distance(parent_position.current) < limit;
+        match state {
+            //bodies that start inside the limit (spacecraft, ...) are held together by more than their gravity
+            None => {
+                commands.entity(entity).insert(RocheState { inside });
+                continue;
+            }
+            Some(state) if state.inside == inside => continue,
+            Some(_) => {}
+        }
+        commands.entity(entity).insert(RocheState { inside });
+        if !inside {
+            continue;
+        }
+        let message = format!("{} crossed the Roche limit of {} ({})", name, parent_name, format_length(limit));
+        toasts.0.add(warning_toast(message.as_str()));
+        event_log.add(sim_time.0, message);
+        if !settings.roche_breakup {
+            continue;
+        }
+        //the fragments share the mass of the body and line up along its orbit on a short arc centred on the body,
+        //shifted so their barycenter and total momentum are the ones of the body
+        let relative_position = position.current - parent_position.current;
+        let relative_velocity = velocity.0 - parent_velocity.0;
+        let normal = relative_position.cross(relative_velocity).normalize_or_zero();
+        let factor = (1.0 / FRAGMENTS as f64).cbrt();
+        let mut fragment_shape = shape.clone();
+        fragment_shape.ellipsoid.semi_major_equatorial_radius_km *= factor;
+        fragment_shape.ellipsoid.semi_minor_equatorial_radius_km *= factor;
+        fragment_shape.ellipsoid.polar_radius_km *= factor;
+        fragment_shape.applied = true;
+        let spacing = f64::min(FRAGMENT_SPACING * fragment_shape.ellipsoid.mean_equatorial_radius_km() * 1000.0 / relative_position.length(), 2.0 * PI / FRAGMENTS as f64);
+        let states: Vec<(DVec3, DVec3)> = (0..FRAGMENTS).map(|index| {
+            let turn = DQuat::from_axis_angle(normal, spacing * (index as f64 - (FRAGMENTS - 1) as f64 / 2.0));
+            (turn * relative_position, turn * relative_velocity)
+        }).collect();
+        let position_shift = relative_position - states.iter().map(|(p, _)| *p).sum::<DVec3>() / FRAGMENTS as f64;
+        let velocity_shift = relative_velocity - states.iter().map(|(_, v)| *v).sum::<DVec3>() / FRAGMENTS as f64;
+        let meshes = fragment_meshes(entity, scene, &scenes, &scene_meshes, &global_transforms, &spawner);
+        let (planet, moon) = markers.get(entity).unwrap_or_default();
+        let mut fragments = Vec::with_capacity(FRAGMENTS);
+        for (index, (fragment_position, fragment_velocity)) in states.into_iter().enumerate() {
+            let mut fragment = commands.spawn(BodyBundle {
+                mass: Mass(mass.0 / FRAGMENTS as f64),
+                transform: Transform::from_scale(transform.scale * factor as f32).with_rotation(transform.rotation),
+                sim_position: SimPosition::new(parent_position.current + fragment_position + position_shift),
+                vel: Velocity(parent_velocity.0 + fragment_velocity + velocity_shift),
+                name: Name::new(format!("{} Fragment {}", name, index + 1)),
+                model_path: model_path.clone(),
+                orbit: OrbitSettings {
+                    color: orbit.color,
+                    ..default()
+                },
+                rotation: *rotation,
+                diameter: fragment_shape.clone(),
+                ..default()
+            });
+            fragment.insert((ApsisBody::default(), BodyParent(parent), RocheState { inside: true }, Visibility::Visible));
+            if planet {
+                fragment.insert(Planet);
+            }
+            if moon {
+                fragment.insert(Moon);
+            }
+            fragment.with_children(|children| {
+                for (mesh, material, mesh_transform) in &meshes {
+                    children.spawn((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone()), *mesh_transform));
+                }
+            });
+            fragments.push(fragment.id());
+        }
+        event_log.add(sim_time.0, format!("{} broke up into {} fragments", name, FRAGMENTS));
+        broken_up.push((entity, parent, fragments));
+    }
+    for (entity, parent, fragments) in broken_up {
+        detach_absorbed(entity, parent, &mut hierarchy, &mut commands);
+        if let Ok((_, Some(mut children))) = hierarchy.get_mut(parent) {
+            children.0.extend(fragments);
+        }
+        if selected_entity.entity == Some(entity) {
+            selected_entity.change_entity(parent, false);
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+//The meshes of the body's scene relative to the body, every fragment shows them with the same handles
+//instead of instantiating the whole scene again
+fn fragment_meshes(
+    entity: Entity,
+    scene: &SceneHandle,
+    scenes: &Query<&SceneInstance>,
+    scene_meshes: &Query<(&Mesh3d, &MeshMaterial3d<StandardMaterial>, &GlobalTransform)>,
+    global_transforms: &Query<&GlobalTransform>,
+    spawner: &SceneSpawner,
+) -> Vec<(Handle<Mesh>, Handle<StandardMaterial>, Transform)> {
+    let (Ok(instance), Ok(body_transform)) = (scenes.get(scene.1), global_transforms.get(entity)) else {
+        return Vec::new();
+    };
+    scene_meshes.iter_many(spawner.iter_instance_entities(**instance))
+        .map(|(mesh, material, mesh_transform)| (mesh.0.clone(), material.0.clone(), mesh_transform.reparented_to(body_transform)))
+        .collect()
+}
@@ -1,7 +1,7 @@
 use bevy::app::{App, Plugin};
 use bevy::prelude::Resource;
 
-use crate::constants::DEFAULT_TIMESTEP;
+use crate::constants::{DEFAULT_TIMESTEP, PHYSICS_TICK_RATE};
 use crate::simulation::units::text_formatter::format_seconds;
 
 pub struct SpeedPlugin;
@@ -15,10 +15,16 @@ impl Plugin for SpeedPlugin {
 
 }
 
+//Simulated seconds per real second added by every sub step
 #[derive(Resource, Debug)]
-pub struct Speed(pub f64); //speed in seconds
+pub struct Speed(pub f64);
     
 impl Speed {
+
+    //simulated seconds a single sub step integrates
+    pub fn step(&self) -> f64 {
+        self.0 / PHYSICS_TICK_RATE
+    }
         
     pub fn format(&self, sub_steps: i32) -> String {
         let speed_in_seconds = self.0 * (sub_steps as f64);
@@ -0,0 +1,127 @@
+use crate::constants::G;
+use crate::simulation::components::body::{BodyParent, Mass, OrbitSettings, SimPosition, Velocity};
+use crate::simulation::components::orbital_elements::OrbitalElements;
+use crate::simulation::components::scale::SimulationScale;
+use crate::simulation::integration::{advance_sim_time, paused, SimulationStep};
+use crate::simulation::ui::event_log::EventLog;
+use crate::simulation::ui::toast::{warning_toast, ToastContainer};
+use crate::simulation::ui::SimTime;
+use crate::simulation::units::text_formatter::format_length;
+use crate::utils::sim_state_type_simulation;
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::color::Alpha;
+use bevy::math::Isometry3d;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::{not, Commands, Component, Entity, Gizmos, IntoScheduleConfigs, Name, Query, Reflect, Res, ResMut, Transform};
+
+const HILL_ALPHA: f32 = 0.15;
+const LAPLACE_ALPHA: f32 = 0.35;
+
+pub struct SphereOfInfluencePlugin;
+
+impl Plugin for SphereOfInfluencePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .register_type::<SphereOfInfluence>()
+            .add_systems(FixedUpdate, (update_spheres_of_influence).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)))
+            .add_systems(Update, (draw_spheres_of_influence.after(SimulationStep)).run_if(sim_state_type_simulation));
+    }
+
+}
+
+//Regions around a body with a parent in which it dominates the motion of smaller bodies, in m
+#[derive(Component, Debug, Clone, Copy, Reflect, Default)]
+pub struct SphereOfInfluence {
+
+    pub hill: f64, //satellites inside stay bound against the tides of the parent
+    pub laplace: f64, //the body perturbs more than the parent, the sphere used for patched conics
+    pub leaves_parent: bool, //the orbit of the body reaches beyond the Laplace sphere of its own parent
+
+}
+
+//Uses the periapsis distance, where the parent pulls hardest
+pub fn hill_radius(semi_major_axis: f64, eccentricity: f64, mass: f64, parent_mass: f64) -> f64 {
+    semi_major_axis * (1.0 - eccentricity) * (mass / (3.0 * parent_mass)).cbrt()
+}
+
+pub fn laplace_radius(semi_major_axis: f64, mass: f64, parent_mass: f64) -> f64 {
+    semi_major_axis * (mass / parent_mass).powf(0.4)
+}
+
+//Unbound orbits have no semi-major axis to measure from, the current distance on a circle stands in for it
+fn sphere_of_influence(elements: &OrbitalElements, distance: f64, mass: f64, parent_mass: f64) -> SphereOfInfluence {
+    let (semi_major_axis, eccentricity) = match elements.apoapsis_distance() {
+        Some(_) => (elements.semi_major_axis, elements.eccentricity),
+        None => (distance, 0.0),
+    };
+    SphereOfInfluence {
+        hill: hill_radius(semi_major_axis, eccentricity, mass, parent_mass),
+        laplace: laplace_radius(semi_major_axis, mass, parent_mass),
+        leaves_parent: false,
+    }
+}
+
+//A child that gets further from its parent than the Laplace sphere of the parent is dominated by the grandparent,
+//so it was most likely assigned to the wrong parent. Bound orbits are judged by their apoapsis, flybys by their current distance.
+//Only the moment an orbit starts leaving the sphere is reported
+fn update_spheres_of_influence(
+    mut bodies: Query<(Entity, &Name, &SimPosition, &Velocity, &Mass, &BodyParent, Option<&mut SphereOfInfluence>)>,
+    parents: Query<(&Name, &SimPosition, &Velocity, &Mass)>,
+    sim_time: Res<SimTime>,
+    mut toasts: ResMut<ToastContainer>,
+    mut event_log: ResMut<EventLog>,
+    mut commands: Commands,
+) {
+    let mut spheres: HashMap<Entity, (SphereOfInfluence, f64)> = HashMap::default();
+    for (entity, _, position, velocity, mass, parent, _) in &bodies {
+        let Ok((_, p_pos, p_vel, p_mass)) = parents.get(parent.0) else {
+            continue;
+        };
+        let relative_position = position.current - p_pos.current;
+        let Some(elements) = OrbitalElements::from_state(relative_position, velocity.0 - p_vel.0, G * (p_mass.0 + mass.0)) else {
+            continue;
+        };
+        let distance = relative_position.length();
+        //an unbound orbit leaves every sphere eventually, only its current distance tells if it already did
+        let furthest = elements.apoapsis_distance().unwrap_or(distance);
+        spheres.insert(entity, (sphere_of_influence(&elements, distance, mass.0, p_mass.0), furthest));
+    }
+    let laplace: HashMap<Entity, f64> = spheres.iter().map(|(entity, (sphere, _))| (*entity, sphere.laplace)).collect();
+    for (entity, name, _, _, _, parent, old_sphere) in &mut bodies {
+        let Some((mut sphere, furthest)) = spheres.get(&entity).copied() else {
+            continue;
+        };
+        let parent_laplace = laplace.get(&parent.0).copied();
+        sphere.leaves_parent = parent_laplace.is_some_and(|radius| furthest > radius);
+        let reported = old_sphere.as_ref().is_some_and(|old| old.leaves_parent);
+        if let (true, false, Some(radius)) = (sphere.leaves_parent, reported, parent_laplace) {
+            if let Ok((parent_name, ..)) = parents.get(parent.0) {
+                let message = format!("The orbit of {} leaves the sphere of influence of {} ({}), its parent is probably wrong", name, parent_name, format_length(radius));
+                toasts.0.add(warning_toast(message.as_str()));
+                event_log.add(sim_time.0, message);
+            }
+        }
+        match old_sphere {
+            Some(mut old_sphere) => *old_sphere = sphere,
+            None => {
+                commands.entity(entity).insert(sphere);
+            }
+        }
+    }
+}
+
+fn draw_spheres_of_influence(
+    bodies: Query<(&Transform, &OrbitSettings, &SphereOfInfluence)>,
+    mut gizmos: Gizmos,
+    scale: Res<SimulationScale>
+) {
+    for (transform, orbit, sphere) in &bodies {
+        if !orbit.draw_soi {
+            continue;
+        }
+        let isometry = Isometry3d::from_translation(transform.translation);
+        gizmos.sphere(isometry, scale.m_to_unit(sphere.hill) as f32, orbit.color.with_alpha(HILL_ALPHA));
+        gizmos.sphere(isometry, scale.m_to_unit(sphere.laplace) as f32, orbit.color.with_alpha(LAPLACE_ALPHA));
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::math::DVec3;
+use bevy::prelude::{Entity, Query, Transform, With, Without};
+use bevy::tasks::{ComputeTaskPool, ParallelSliceMut, TaskPool};
+
+use crate::constants::G;
+use crate::simulation::components::body::{Acceleration, KeplerOrbit, Mass, OrbitSettings, SimPosition, TestParticle, Velocity};
+use crate::simulation::integration::barnes_hut::Octree;
+use crate::simulation::integration::ephemeris::Ephemerides;
+use crate::simulation::integration::perturbation::Perturbations;
+use crate::simulation::integration::{ForceModel, ForceSettings};
+
+const MIN_BODIES_PER_TASK: usize = 32; //spawning tasks for a handful of bodies is slower than doing it on one thread
+
+//Computes the gravitational acceleration of every body on the compute task pool.
+//Every body sums up the other bodies in the same order no matter how the work is split up,
+//so the result is bit-identical for any number of threads.
+//Bodies without mass (test particles) are skipped as sources, so they only cost one pass over the massive bodies.
+pub fn calculate_accelerations(
+    positions: &[DVec3],
+    velocities: &[DVec3],
+    masses: &[f64],
+    settings: &ForceSettings,
+    perturbations: &Perturbations,
+) -> Vec<DVec3> {
+    calculate_accelerations_on(ComputeTaskPool::get(), positions, velocities, masses, settings, perturbations)
+}
+
+fn calculate_accelerations_on(
+    task_pool: &TaskPool,
+    positions: &[DVec3],
+    velocities: &[DVec3],
+    masses: &[f64],
+    settings: &ForceSettings,
+    perturbations: &Perturbations,
+) -> Vec<DVec3> {
+    let mut accelerations = match settings.model {
+        ForceModel::Direct => {
+            let sources: Vec<usize> = (0..positions.len()).filter(|index| masses[*index] > 0.0).collect();
+            for_each_body(task_pool, positions.len(), |index| direct_acceleration(index, positions, masses, &sources, settings.softening))
+        }
+        ForceModel::BarnesHut => {
+            //bodies that make up a noticeable part of the total mass are always summed exactly
+            let threshold = masses.iter().sum::<f64>() * settings.exact_mass_ratio;
+            let (massive, light): (Vec<usize>, Vec<usize>) = (0..positions.len()).filter(|index| masses[*index] > 0.0).partition(|index| masses[*index] >= threshold);
+            let tree = Octree::new(positions, masses, light);
+            for_each_body(task_pool, positions.len(), |index| {
+                direct_acceleration(index, positions, masses, &massive, settings.softening) + tree.acceleration(index, settings.theta, settings.softening)
+            })
+        }
+    };
+    perturbations.apply(positions, velocities, masses, &mut accelerations);
+    accelerations
+}
+
+fn for_each_body(
+    task_pool: &TaskPool,
+    count: usize,
+    acceleration: impl Fn(usize) -> DVec3 + Send + Sync,
+) -> Vec<DVec3> {
+    let mut accelerations = vec![DVec3::ZERO; count];
+    if count == 0 {
+        return accelerations;
+    }
+    let chunk_size = usize::max(MIN_BODIES_PER_TASK, count.div_ceil(task_pool.thread_num()));
+    accelerations.par_chunk_map_mut(task_pool, chunk_size, |chunk_index, chunk| {
+        let offset = chunk_index * chunk_size;
+        for (index, value) in chunk.iter_mut().enumerate() {
+            *value = acceleration(offset + index);
+        }
+    });
+    accelerations
+}
+
+fn direct_acceleration(
+    index: usize,
+    positions: &[DVec3],
+    masses: &[f64],
+    others: &[usize],
+    softening: f64,
+) -> DVec3 {
+    let mut acceleration = DVec3::ZERO;
+    for other in others {
+        if *other != index {
+            acceleration += pairwise_acceleration(positions[index], positions[*other], masses[*other], softening);
+        }
+    }
+    acceleration
+}
+
+//Acceleration of a body at the given position caused by a mass at another position.
+//The softening length (Plummer) keeps the force finite when two bodies get very close.
+pub fn pairwise_acceleration(
+    position: DVec3,
+    other_position: DVec3,
+    other_mass: f64,
+    softening: f64,
+) -> DVec3 {
+    let distance = other_position - position;
+    let r_sq = distance.length_squared() + softening * softening;
+    distance * (G * other_mass / (r_sq * r_sq.sqrt()))
+}
+
+//The mass a body attracts others with, test particles don't attract anything
+pub fn gravitational_mass(
+    entity: Entity,
+    mass: &Mass,
+    test_particles: &Query<(), With<TestParticle>>,
+) -> f64 {
+    if test_particles.contains(entity) {
+        0.0
+    } else {
+        mass.0
+    }
+}
+
+//Snapshots the bodies into flat arrays and stores the acceleration of every body in its Acceleration component
+pub fn update_acceleration(
+    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    test_particles: &Query<(), With<TestParticle>>,
+    settings: &ForceSettings,
+    perturbations: &Perturbations,
+    ephemerides: &Ephemerides,
+) {
+    follow_ephemerides(query, ephemerides);
+    let masses: Vec<f64> = query.iter().map(|(entity, mass, _, _, _, _, _)| gravitational_mass(entity, mass, test_particles)).collect();
+    let positions: Vec<DVec3> = query.iter().map(|(_, _, _, _, _, pos, _)| pos.current).collect();
+    let velocities: Vec<DVec3> = query.iter().map(|(_, _, _, _, vel, _, _)| vel.0).collect();
+    let accelerations = calculate_accelerations(&positions, &velocities, &masses, settings, perturbations);
+    for ((_, _, mut acc, _, _, _, _), acceleration) in query.iter_mut().zip(accelerations) {
+        acc.0 = acceleration;
+    }
+}
+
+//Moves the ephemeris driven bodies to where the kernels have them at the time the integration has reached
+pub fn follow_ephemerides(
+    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    ephemerides: &Ephemerides,
+) {
+    if ephemerides.is_empty() {
+        return;
+    }
+    let mut positions: Vec<DVec3> = query.iter().map(|(_, _, _, _, _, pos, _)| pos.current).collect();
+    let mut velocities: Vec<DVec3> = query.iter().map(|(_, _, _, _, vel, _, _)| vel.0).collect();
+    ephemerides.apply(0.0, &mut positions, &mut velocities);
+    for (index, (_, _, _, _, mut vel, mut pos, _)) in query.iter_mut().enumerate() {
+        if ephemerides.contains(index) {
+            pos.current = positions[index];
+            vel.0 = velocities[index];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use bevy::tasks::TaskPoolBuilder;
+
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::{ForceModel, ForceSettings};
+
+    use super::{calculate_accelerations_on, MIN_BODIES_PER_TASK};
+
+    //A fixed cluster with enough bodies to be split into several tasks, every fifth body is massless
+    fn bodies() -> (Vec<DVec3>, Vec<DVec3>, Vec<f64>) {
+        let count = MIN_BODIES_PER_TASK * 7 + 5;
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+        };
+        let mut positions = Vec::with_capacity(count);
+        let mut velocities = Vec::with_capacity(count);
+        let mut masses = Vec::with_capacity(count);
+        for index in 0..count {
+            positions.push(DVec3::new(next(), next(), next()) * 1e12);
+            velocities.push(DVec3::new(next(), next(), next()) * 3e4);
+            masses.push(if index % 5 == 0 { 0.0 } else { (next() + 1.0) * 1e27 });
+        }
+        (positions, velocities, masses)
+    }
+
+    #[test]
+    fn same_result_for_any_thread_count() {
+        let (positions, velocities, masses) = bodies();
+        let single = TaskPoolBuilder::new().num_threads(1).build();
+        let multi = TaskPoolBuilder::new().num_threads(8).build();
+        let perturbations = Perturbations::default();
+        for model in [ForceModel::Direct, ForceModel::BarnesHut] {
+            let settings = ForceSettings { model, softening: 1e6, ..Default::default() };
+            let expected = calculate_accelerations_on(&single, &positions, &velocities, &masses, &settings, &perturbations);
+            let result = calculate_accelerations_on(&multi, &positions, &velocities, &masses, &settings, &perturbations);
+            assert_eq!(expected, result);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+use bevy::math::DVec3;
+
+use crate::simulation::integration::acceleration::pairwise_acceleration;
+
+const MAX_DEPTH: usize = 32; //bodies at (almost) the same position would otherwise split forever
+
+struct Node {
+    center: DVec3,
+    half_size: f64,
+    mass: f64,
+    center_of_mass: DVec3,
+    children: Vec<usize>,
+    bodies: Range<usize>, //the bodies of this node in Octree::bodies
+}
+
+//Barnes-Hut octree over a subset of the bodies, distant nodes are approximated by their center of mass
+pub struct Octree<'a> {
+    positions: &'a [DVec3],
+    masses: &'a [f64],
+    nodes: Vec<Node>,
+    bodies: Vec<usize>,
+}
+
+impl<'a> Octree<'a> {
+
+    pub fn new(positions: &'a [DVec3], masses: &'a [f64], mut bodies: Vec<usize>) -> Self {
+        let (min, max) = bodies.iter().fold((DVec3::INFINITY, DVec3::NEG_INFINITY), |(min, max), index| {
+            (min.min(positions[*index]), max.max(positions[*index]))
+        });
+        let mut tree = Octree { positions, masses, nodes: Vec::new(), bodies: Vec::new() };
+        if !bodies.is_empty() {
+            let center = (min + max) / 2.0;
+            let half_size = f64::max((max - min).max_element() / 2.0, f64::MIN_POSITIVE);
+            tree.build(&mut bodies, 0, center, half_size, 0);
+            tree.bodies = bodies;
+        }
+        tree
+    }
+
+    fn build(&mut self, bodies: &mut [usize], offset: usize, center: DVec3, half_size: f64, depth: usize) -> usize {
+        let positions = self.positions;
+        let mass: f64 = bodies.iter().map(|index| self.masses[*index]).sum();
+        let center_of_mass = if mass > 0.0 {
+            bodies.iter().map(|index| positions[*index] * self.masses[*index]).sum::<DVec3>() / mass
+        } else {
+            center
+        };
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            center,
+            half_size,
+            mass,
+            center_of_mass,
+            children: Vec::new(),
+            bodies: offset..offset + bodies.len(),
+        });
+        if bodies.len() <= 1 || depth >= MAX_DEPTH {
+            return node;
+        }
+        //a stable sort keeps the tree identical between runs
+        bodies.sort_by_key(|index| octant(center, positions[*index]));
+        let mut children = Vec::with_capacity(8);
+        let mut start = 0;
+        for octant_index in 0..8 {
+            let end = start + bodies[start..].iter().take_while(|index| octant(center, positions[**index]) == octant_index).count();
+            if end > start {
+                let child_center = center + octant_offset(octant_index) * (half_size / 2.0);
+                children.push(self.build(&mut bodies[start..end], offset + start, child_center, half_size / 2.0, depth + 1));
+            }
+            start = end;
+        }
+        self.nodes[node].children = children;
+        node
+    }
+
+    //acceleration at the position of the given body, the body itself is skipped
+    pub fn acceleration(&self, index: usize, theta: f64, softening: f64) -> DVec3 {
+        let mut acceleration = DVec3::ZERO;
+        if self.nodes.is_empty() {
+            return acceleration;
+        }
+        let position = self.positions[index];
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.mass == 0.0 {
+                continue;
+            }
+            if node.children.is_empty() {
+                for body in &self.bodies[node.bodies.clone()] {
+                    if *body != index {
+                        acceleration += pairwise_acceleration(position, self.positions[*body], self.masses[*body], softening);
+                    }
+                }
+                continue;
+            }
+            let size = node.half_size * 2.0;
+            let inside = ((position - node.center).abs() - node.half_size).max_element() <= 0.0;
+            if !inside && size * size < theta * theta * node.center_of_mass.distance_squared(position) {
+                acceleration += pairwise_acceleration(position, node.center_of_mass, node.mass, softening);
+            } else {
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        acceleration
+    }
+
+}
+
+fn octant(center: DVec3, position: DVec3) -> usize {
+    (position.x >= center.x) as usize | (((position.y >= center.y) as usize) << 1) | (((position.z >= center.z) as usize) << 2)
+}
+
+fn octant_offset(octant: usize) -> DVec3 {
+    DVec3::new(
+        if octant & 1 != 0 { 1.0 } else { -1.0 },
+        if octant & 2 != 0 { 1.0 } else { -1.0 },
+        if octant & 4 != 0 { 1.0 } else { -1.0 },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use crate::simulation::integration::acceleration::calculate_accelerations;
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::{ForceModel, ForceSettings};
+
+    //A random cluster of 500 bodies with similar masses, so the tree is used for all of them unless exact_mass_ratio says otherwise
+    fn cluster() -> (Vec<DVec3>, Vec<DVec3>, Vec<f64>) {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+        };
+        let positions: Vec<DVec3> = (0..500).map(|_| DVec3::new(next(), next(), next()) * 1e12).collect();
+        let masses: Vec<f64> = (0..500).map(|_| (next() + 2.0) * 1e27).collect();
+        (positions, vec![DVec3::ZERO; 500], masses)
+    }
+
+    fn relative_errors(settings: &ForceSettings) -> Vec<f64> {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let (positions, velocities, masses) = cluster();
+        let perturbations = Perturbations::default();
+        let direct = calculate_accelerations(&positions, &velocities, &masses, &ForceSettings { model: ForceModel::Direct, ..settings.clone() }, &perturbations);
+        let tree = calculate_accelerations(&positions, &velocities, &masses, settings, &perturbations);
+        direct.iter().zip(&tree).map(|(direct, tree)| (*tree - *direct).length() / direct.length()).collect()
+    }
+
+    #[test]
+    fn close_to_direct_sum() {
+        let errors = relative_errors(&ForceSettings { model: ForceModel::BarnesHut, theta: 0.5, exact_mass_ratio: 1.0, ..Default::default() });
+        let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+        let max = errors.iter().cloned().fold(0.0, f64::max);
+        assert!(mean < 1e-2, "mean relative error {}", mean);
+        assert!(max < 1e-1, "largest relative error {}", max);
+    }
+
+    //Without an opening angle every node is opened, only the order of the sum differs from the direct one
+    #[test]
+    fn opens_every_node_at_zero_theta() {
+        let errors = relative_errors(&ForceSettings { model: ForceModel::BarnesHut, theta: 0.0, exact_mass_ratio: 1.0, ..Default::default() });
+        assert!(errors.iter().all(|error| *error < 1e-12), "largest relative error {}", errors.iter().cloned().fold(0.0, f64::max));
+    }
+
+    //Every body is above the default exact_mass_ratio here, so nothing goes into the tree
+    #[test]
+    fn massive_bodies_are_summed_exactly() {
+        let errors = relative_errors(&ForceSettings { model: ForceModel::BarnesHut, theta: 0.5, ..Default::default() });
+        assert!(errors.iter().all(|error| *error == 0.0));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::DVec3;
+use bevy::prelude::{not, IntoScheduleConfigs, Local, Query, Res, ResMut, Resource, Without};
+
+use crate::constants::G;
+use crate::simulation::components::body::{KeplerOrbit, Mass, SimPosition, TestParticle, Velocity};
+use crate::simulation::integration::{advance_sim_time, paused, ForceSettings};
+use crate::simulation::ui::SimTime;
+use crate::utils::sim_state_type_simulation;
+
+const SAMPLE_INTERVAL: u32 = 10; //physics steps between two samples, the potential energy is a sum over all pairs
+const MAX_SAMPLES: usize = 300;
+
+pub struct ConservationPlugin;
+
+impl Plugin for ConservationPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Conservation>()
+            .add_systems(FixedUpdate, (sample_conserved_quantities).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
+    }
+
+}
+
+//Quantities an isolated N-body system keeps constant, used to judge how trustworthy the integration is
+#[derive(Clone, Copy, Default)]
+pub struct ConservedQuantities {
+
+    pub time: f64, //simulated seconds
+    pub energy: f64, //in J
+    pub momentum: DVec3, //in kg m/s
+    pub angular_momentum: DVec3, //in kg m²/s, around the origin
+    pub barycenter: DVec3, //in m
+    momentum_scale: f64, //sum of |m * v|, the total momentum is usually close to zero so its drift is relative to this
+
+}
+
+impl ConservedQuantities {
+
+    pub fn energy_drift(&self, start: &ConservedQuantities) -> f64 {
+        relative_drift(self.energy - start.energy, start.energy.abs())
+    }
+
+    pub fn momentum_drift(&self, start: &ConservedQuantities) -> f64 {
+        relative_drift((self.momentum - start.momentum).length(), start.momentum_scale)
+    }
+
+    pub fn angular_momentum_drift(&self, start: &ConservedQuantities) -> f64 {
+        relative_drift((self.angular_momentum - start.angular_momentum).length(), start.angular_momentum.length())
+    }
+
+}
+
+fn relative_drift(difference: f64, scale: f64) -> f64 {
+    if scale == 0.0 {
+        difference.abs()
+    } else {
+        difference.abs() / scale
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Conservation {
+
+    pub start: Option<ConservedQuantities>,
+    pub current: Option<ConservedQuantities>,
+    pub history: VecDeque<ConservedQuantities>,
+
+}
+
+//Only the bodies that attract each other are part of the closed system,
+//test particles and Kepler bodies don't pull back on anything
+fn sample_conserved_quantities(
+    bodies: Query<(&Mass, &SimPosition, &Velocity), (Without<TestParticle>, Without<KeplerOrbit>)>,
+    settings: Res<ForceSettings>,
+    sim_time: Res<SimTime>,
+    mut conservation: ResMut<Conservation>,
+    mut steps: Local<u32>,
+) {
+    *steps += 1;
+    if conservation.start.is_some() && *steps < SAMPLE_INTERVAL {
+        return;
+    }
+    *steps = 0;
+    if bodies.is_empty() {
+        return;
+    }
+    let sample = conserved_quantities(&bodies, settings.softening, sim_time.0);
+    if conservation.start.is_none() {
+        conservation.start = Some(sample);
+    }
+    conservation.current = Some(sample);
+    conservation.history.push_back(sample);
+    if conservation.history.len() > MAX_SAMPLES {
+        conservation.history.pop_front();
+    }
+}
+
+fn conserved_quantities(
+    bodies: &Query<(&Mass, &SimPosition, &Velocity), (Without<TestParticle>, Without<KeplerOrbit>)>,
+    softening: f64,
+    time: f64,
+) -> ConservedQuantities {
+    let states: Vec<(f64, DVec3, DVec3)> = bodies.iter().map(|(mass, position, velocity)| (mass.0, position.current, velocity.0)).collect();
+    let mut quantities = ConservedQuantities {
+        time,
+        ..ConservedQuantities::default()
+    };
+    let mut total_mass = 0.0;
+    for (index, (mass, position, velocity)) in states.iter().enumerate() {
+        quantities.energy += 0.5 * mass * velocity.length_squared();
+        quantities.momentum += *velocity * *mass;
+        quantities.momentum_scale += mass * velocity.length();
+        quantities.angular_momentum += position.cross(*velocity) * *mass;
+        quantities.barycenter += *position * *mass;
+        total_mass += mass;
+        //same softened potential as the force, otherwise the energy drifts on close encounters
+        for (other_mass, other_position, _) in &states[index + 1..] {
+            let r = (position.distance_squared(*other_position) + softening * softening).sqrt();
+            if r > 0.0 {
+                quantities.energy -= G * mass * other_mass / r;
+            }
+        }
+    }
+    if total_mass > 0.0 {
+        quantities.barycenter /= total_mass;
+    }
+    quantities
+}
@@ -0,0 +1,270 @@
+use std::time::{Duration, Instant};
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::diagnostic::Diagnostics;
+use bevy::math::DVec3;
+use bevy::prelude::{in_state, not, Entity, IntoScheduleConfigs, Local, Query, Res, ResMut, Time, Transform, With, Without};
+
+use crate::simulation::components::body::{Acceleration, KeplerOrbit, Mass, OrbitSettings, SimPosition, TestParticle, Velocity};
+use crate::simulation::integration::acceleration::{calculate_accelerations, gravitational_mass};
+use crate::simulation::integration::ephemeris::{Ephemerides, EphemerisSources};
+use crate::simulation::integration::perturbation::{PerturbationSources, Perturbations};
+use crate::simulation::integration::{paused, AdaptiveStepStats, AdaptiveTolerance, ForceSettings, IntegrationType, PhysicsStep, StepSpan, NBODY_ACCEPTED_STEPS, NBODY_REJECTED_STEPS, NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
+use crate::simulation::ui::toast::{warning_toast, ToastContainer};
+use crate::utils::sim_state_type_simulation;
+
+//Dormand-Prince 5(4) tableau, the last row is also the 5th order solution (first same as last)
+const A: [&[f64]; 7] = [
+    &[],
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0],
+    &[9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0],
+    &[35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+
+//fraction of the step at which each stage is evaluated
+const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+
+//difference between the 5th and the embedded 4th order weights
+const E: [f64; 7] = [71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0];
+
+const SAFETY: f64 = 0.9;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+const MAX_ATTEMPTS_PER_STEP: u32 = 100_000; //prevents the app from freezing when the tolerance can't be reached
+
+pub struct DormandPrinceIntegrationPlugin;
+
+impl Plugin for DormandPrinceIntegrationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (apply_physics).in_set(PhysicsStep).run_if(sim_state_type_simulation).run_if(in_state(IntegrationType::DormandPrince)).run_if(not(paused)));
+    }
+}
+
+//What the accelerations depend on besides the state
+struct Model<'a> {
+    masses: &'a [f64],
+    forces: &'a ForceSettings,
+    perturbations: &'a Perturbations,
+}
+
+//Positions, velocities and accelerations of all bodies at the same time
+struct State {
+    positions: Vec<DVec3>,
+    velocities: Vec<DVec3>,
+    accelerations: Vec<DVec3>,
+}
+
+struct StepResult {
+    state: State,
+    error: f64,
+}
+
+//What an adaptive run over one physics step achieved
+struct Progress {
+    elapsed: f64, //simulated seconds, less than the target if the attempts ran out
+    accepted: u32,
+    rejected: u32,
+    last_step: Duration,
+}
+
+fn apply_physics(
+    mut query: Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    time: Res<Time>,
+    mut span: ResMut<StepSpan>,
+    forces: Res<ForceSettings>,
+    test_particles: Query<(), With<TestParticle>>,
+    perturbation_sources: PerturbationSources,
+    ephemeris_sources: EphemerisSources,
+    tolerance: Res<AdaptiveTolerance>,
+    mut stats: ResMut<AdaptiveStepStats>,
+    mut toasts: ResMut<ToastContainer>,
+    mut falling_behind: Local<bool>,
+    mut diagnostics: Diagnostics,
+) {
+    let start = Instant::now();
+    let delta = time.delta_secs_f64();
+    let perturbations = perturbation_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let mut ephemerides = ephemeris_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let masses: Vec<f64> = query.iter().map(|(entity, mass, _, _, _, _, _)| gravitational_mass(entity, mass, &test_particles)).collect();
+    let mut positions: Vec<DVec3> = query.iter().map(|(_, _, _, _, _, pos, _)| pos.current).collect();
+    let mut velocities: Vec<DVec3> = query.iter().map(|(_, _, _, _, vel, _, _)| vel.0).collect();
+    ephemerides.apply(0.0, &mut positions, &mut velocities);
+    let model = Model { masses: &masses, forces: &forces, perturbations: &perturbations };
+    let accelerations = calculate_accelerations(&positions, &velocities, &masses, &forces, &perturbations);
+    let mut state = State { positions, velocities, accelerations };
+    if stats.step <= 0.0 {
+        stats.step = span.0;
+    }
+    let progress = integrate(&model, &mut state, span.0, &mut stats.step, &tolerance, &mut ephemerides);
+    //the sim time only moves as far as the bodies did
+    if progress.elapsed < span.0 {
+        span.0 = progress.elapsed;
+        if !*falling_behind {
+            toasts.0.add(warning_toast("The adaptive step got too small to keep up, the simulation runs slower than the set speed"));
+        }
+        *falling_behind = true;
+    } else {
+        *falling_behind = false;
+    }
+    diagnostics.add_measurement(&NBODY_STEP_TIME, || progress.last_step.as_nanos() as f64);
+    for (index, (_, _, mut acc, _, mut vel, mut pos, _)) in query.iter_mut().enumerate() {
+        acc.0 = state.accelerations[index];
+        vel.0 = state.velocities[index];
+        pos.current = state.positions[index];
+    }
+    stats.accepted += progress.accepted as u64;
+    stats.rejected += progress.rejected as u64;
+    diagnostics.add_measurement(&NBODY_TOTAL_TIME, || start.elapsed().as_nanos() as f64);
+    diagnostics.add_measurement(&NBODY_STEPS, || progress.accepted as f64 / delta);
+    diagnostics.add_measurement(&NBODY_ACCEPTED_STEPS, || progress.accepted as f64);
+    diagnostics.add_measurement(&NBODY_REJECTED_STEPS, || progress.rejected as f64);
+}
+
+//Takes adaptive steps until the target time is covered or MAX_ATTEMPTS_PER_STEP is reached.
+//The step size carries over to the next call
+fn integrate(
+    model: &Model,
+    state: &mut State,
+    target: f64,
+    step_size: &mut f64,
+    tolerance: &AdaptiveTolerance,
+    ephemerides: &mut Ephemerides,
+) -> Progress {
+    let mut progress = Progress {
+        elapsed: 0.0,
+        accepted: 0,
+        rejected: 0,
+        last_step: Duration::ZERO,
+    };
+    while progress.elapsed < target && progress.accepted + progress.rejected < MAX_ATTEMPTS_PER_STEP {
+        let timestep = f64::min(*step_size, target - progress.elapsed);
+        let start_step = Instant::now();
+        let result = step(model, state, timestep, tolerance, ephemerides);
+        progress.last_step = start_step.elapsed();
+        let factor = if result.error == 0.0 {
+            MAX_FACTOR
+        } else {
+            (SAFETY * result.error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR)
+        };
+        if result.error <= 1.0 {
+            *state = result.state;
+            progress.elapsed += timestep;
+            ephemerides.advance(timestep);
+            progress.accepted += 1;
+            //don't let the shortened last step shrink the step for the next physics step
+            if timestep >= *step_size {
+                *step_size = timestep * factor;
+            }
+        } else {
+            progress.rejected += 1;
+            *step_size = timestep * f64::min(factor, 1.0);
+        }
+    }
+    progress
+}
+
+//The ephemeris bodies are placed where they are at the time of each stage and left out of the error
+fn step(
+    model: &Model,
+    state: &State,
+    timestep: f64,
+    tolerance: &AdaptiveTolerance,
+    ephemerides: &Ephemerides,
+) -> StepResult {
+    let positions = &state.positions;
+    let velocities = &state.velocities;
+    let mut stage_velocities: Vec<Vec<DVec3>> = vec![velocities.to_vec()];
+    let mut stage_accelerations: Vec<Vec<DVec3>> = vec![state.accelerations.to_vec()];
+    let mut new_positions = positions.to_vec();
+    for (row, c) in A.iter().zip(C).skip(1) {
+        new_positions = combine(positions, &stage_velocities, row, timestep);
+        let mut new_stage_velocities = combine(velocities, &stage_accelerations, row, timestep);
+        ephemerides.apply(c * timestep, &mut new_positions, &mut new_stage_velocities);
+        stage_accelerations.push(calculate_accelerations(&new_positions, &new_stage_velocities, model.masses, model.forces, model.perturbations));
+        stage_velocities.push(new_stage_velocities);
+    }
+    let new_velocities = stage_velocities.last().unwrap().clone();
+    let new_accelerations = stage_accelerations.last().unwrap().clone();
+
+    let mut error_sum = 0.0;
+    let mut integrated = 0;
+    for index in (0..positions.len()).filter(|index| !ephemerides.contains(*index)) {
+        integrated += 1;
+        let position_error = timestep * E.iter().enumerate().map(|(stage, e)| stage_velocities[stage][index] * *e).sum::<DVec3>();
+        let velocity_error = timestep * E.iter().enumerate().map(|(stage, e)| stage_accelerations[stage][index] * *e).sum::<DVec3>();
+        let position_scale = tolerance.absolute + tolerance.relative * f64::max(positions[index].length(), new_positions[index].length());
+        let velocity_scale = tolerance.absolute + tolerance.relative * f64::max(velocities[index].length(), new_velocities[index].length());
+        error_sum += (position_error.length() / position_scale).powi(2) + (velocity_error.length() / velocity_scale).powi(2);
+    }
+    let error = if integrated == 0 {
+        0.0
+    } else {
+        (error_sum / (2 * integrated) as f64).sqrt()
+    };
+    StepResult {
+        state: State {
+            positions: new_positions,
+            velocities: new_velocities,
+            accelerations: new_accelerations,
+        },
+        error,
+    }
+}
+
+fn combine(
+    values: &[DVec3],
+    derivatives: &[Vec<DVec3>],
+    weights: &[f64],
+    timestep: f64,
+) -> Vec<DVec3> {
+    values.iter().enumerate().map(|(index, value)| {
+        *value + timestep * weights.iter().zip(derivatives).map(|(weight, derivative)| derivative[index] * *weight).sum::<DVec3>()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use crate::constants::G;
+    use crate::simulation::integration::acceleration::calculate_accelerations;
+    use crate::simulation::integration::ephemeris::Ephemerides;
+    use crate::simulation::integration::kepler::kepler_drift;
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::{AdaptiveTolerance, ForceSettings};
+
+    use super::{integrate, Model, State};
+
+    const DAY: f64 = 86400.0;
+
+    //Sun and an eccentric, inclined Earth over 400 days in 10 day physics steps, against the closed form two body solution
+    #[test]
+    fn reproduces_two_body_orbit() {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let masses = [1.989e30, 5.972e24];
+        let positions = vec![DVec3::ZERO, DVec3::new(1.496e11, 0.0, 0.0)];
+        let velocities = vec![DVec3::ZERO, DVec3::new(0.0, 32_000.0, 1_000.0)];
+        let forces = ForceSettings::default();
+        let perturbations = Perturbations::default();
+        let tolerance = AdaptiveTolerance::default();
+        let accelerations = calculate_accelerations(&positions, &velocities, &masses, &forces, &perturbations);
+        let (start_position, start_velocity) = (positions[1] - positions[0], velocities[1] - velocities[0]);
+        let model = Model { masses: &masses, forces: &forces, perturbations: &perturbations };
+        let mut state = State { positions, velocities, accelerations };
+        let mut step_size = DAY;
+        for _ in 0..40 {
+            let progress = integrate(&model, &mut state, 10.0 * DAY, &mut step_size, &tolerance, &mut Ephemerides::default());
+            assert!((progress.elapsed - 10.0 * DAY).abs() < 1e-6, "stopped after {} s", progress.elapsed);
+        }
+        let (position, velocity) = kepler_drift(start_position, start_velocity, G * masses.iter().sum::<f64>(), 400.0 * DAY);
+        let position_error = (state.positions[1] - state.positions[0] - position).length() / position.length();
+        let velocity_error = (state.velocities[1] - state.velocities[0] - velocity).length() / velocity.length();
+        assert!(position_error < 1e-6, "relative position error {}", position_error);
+        assert!(velocity_error < 1e-6, "relative velocity error {}", velocity_error);
+    }
+}
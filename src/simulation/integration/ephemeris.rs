@@ -0,0 +1,79 @@
+use anise::constants::frames::SSB_J2000;
+use anise::constants::orientations::J2000;
+use anise::prelude::{Almanac, Frame};
+use bevy::ecs::system::SystemParam;
+use bevy::math::DVec3;
+use bevy::prelude::{Entity, Query, Res, With};
+
+use crate::simulation::components::anise::{epoch_at, vector3_to_dvec3, AlmanacHolder};
+use crate::simulation::components::body::EphemerisDriven;
+use crate::simulation::components::horizons::AniseMetadata;
+use crate::simulation::scenario::setup::ScenarioData;
+use crate::simulation::ui::SimTime;
+
+//Queries the bodies whose state comes from the loaded SPICE kernels instead of the integration
+#[derive(SystemParam)]
+pub struct EphemerisSources<'w, 's> {
+    bodies: Query<'w, 's, &'static AniseMetadata, With<EphemerisDriven>>,
+    almanac: Res<'w, AlmanacHolder>,
+    scenario: Res<'w, ScenarioData>,
+    sim_time: Res<'w, SimTime>,
+}
+
+impl EphemerisSources<'_, '_> {
+
+    //The entities have to be in the same order as the positions the ephemerides are applied to later
+    pub fn collect(&self, entities: impl Iterator<Item = Entity>) -> Ephemerides<'_> {
+        let bodies: Vec<(usize, i32)> = entities.enumerate()
+            .filter_map(|(index, entity)| self.bodies.get(entity).ok().map(|metadata| (index, metadata.ephemeris_id)))
+            .collect();
+        Ephemerides {
+            source: (!bodies.is_empty()).then_some((&self.almanac.0, &*self.scenario)),
+            start: self.sim_time.0,
+            time: 0.0,
+            bodies,
+        }
+    }
+
+}
+
+//The ephemeris driven bodies of one physics step. They aren't integrated, every force evaluation places them
+//where the kernels have them at the time of the evaluation, so they still attract the other bodies
+#[derive(Default)]
+pub struct Ephemerides<'a> {
+    source: Option<(&'a Almanac, &'a ScenarioData)>,
+    start: f64, //sim time at the start of the physics step
+    time: f64, //seconds into the physics step the integration has reached
+    bodies: Vec<(usize, i32)>, //index and ephemeris id
+}
+
+impl Ephemerides<'_> {
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.bodies.iter().any(|(body, _)| *body == index)
+    }
+
+    pub fn advance(&mut self, seconds: f64) {
+        self.time += seconds;
+    }
+
+    //Places the driven bodies where they are the given number of seconds after the time reached so far.
+    //Bodies the kernels don't cover keep their values, the coverage is checked before every physics step
+    pub fn apply(&self, offset: f64, positions: &mut [DVec3], velocities: &mut [DVec3]) {
+        let Some((almanac, scenario)) = self.source else {
+            return;
+        };
+        let seconds = self.start + self.time + offset;
+        let epoch = epoch_at(scenario, seconds);
+        for (index, id) in &self.bodies {
+            if let Ok(state) = almanac.translate(Frame::new(*id, J2000), SSB_J2000, epoch, None) {
+                (positions[*index], velocities[*index]) = scenario.to_simulation_frame(seconds, vector3_to_dvec3(state.radius_km) * 1000.0, vector3_to_dvec3(state.velocity_km_s) * 1000.0);
+            }
+        }
+    }
+
+}
@@ -0,0 +1,208 @@
+use std::f64::consts::PI;
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::DVec3;
+use bevy::prelude::{not, Entity, IntoScheduleConfigs, Query, Res};
+
+use crate::constants::G;
+use crate::simulation::components::body::{BodyParent, KeplerOrbit, Mass, SimPosition, Velocity};
+use crate::simulation::integration::{advance_sim_time, paused, PhysicsStep, StepSpan};
+use crate::utils::sim_state_type_simulation;
+
+const MAX_ITERATIONS: usize = 50;
+const TOLERANCE: f64 = 1e-12;
+
+pub struct KeplerOrbitPlugin;
+
+impl Plugin for KeplerOrbitPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (start_kepler_orbits).before(PhysicsStep).run_if(sim_state_type_simulation).run_if(not(paused)))
+            .add_systems(FixedUpdate, (propagate_kepler_orbits).after(PhysicsStep).before(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)));
+    }
+
+}
+
+//Takes the state relative to the parent before the first step and after the body was edited (e.g. in the body panel),
+//while the parent hasn't moved yet
+fn start_kepler_orbits(
+    mut bodies: Query<(&mut KeplerOrbit, &SimPosition, &Velocity, Option<&BodyParent>)>,
+    parents: Query<(&SimPosition, &Velocity)>,
+) {
+    for (mut orbit, position, velocity, parent) in &mut bodies {
+        if orbit.relative.is_some() && orbit.written == Some((position.current, velocity.0)) {
+            continue;
+        }
+        let (parent_position, parent_velocity) = parent.and_then(|p| parents.get(p.0).ok()).map(|(p, v)| (p.current, v.0)).unwrap_or_default();
+        orbit.relative = Some((position.current - parent_position, velocity.0 - parent_velocity));
+    }
+}
+
+//Moves the Kepler bodies along their orbits after the integrated bodies have been updated.
+//Parents are handled before their children, so a Kepler moon can orbit a Kepler planet
+pub fn propagate_kepler_orbits(
+    mut orbits: Query<(Entity, &mut KeplerOrbit, Option<&BodyParent>)>,
+    mut states: Query<(&mut SimPosition, &mut Velocity)>,
+    masses: Query<&Mass>,
+    parents: Query<&BodyParent>,
+    span: Res<StepSpan>,
+) {
+    let span = span.0;
+    let mut order: Vec<(usize, Entity)> = orbits.iter().map(|(entity, _, _)| (depth(entity, &parents), entity)).collect();
+    order.sort_by_key(|(depth, _)| *depth);
+    for (_, entity) in order {
+        let Ok((_, mut orbit, parent)) = orbits.get_mut(entity) else {
+            continue;
+        };
+        let Some((position, velocity)) = orbit.relative else {
+            continue;
+        };
+        let parent = parent.map(|p| p.0);
+        //without a parent the body just keeps its velocity
+        let mu = match parent {
+            Some(parent) => G * (masses.get(entity).map_or(0.0, |m| m.0) + masses.get(parent).map_or(0.0, |m| m.0)),
+            None => 0.0
+        };
+        let (position, velocity) = kepler_drift(position, velocity, mu, span);
+        orbit.relative = Some((position, velocity));
+        let (parent_position, parent_velocity) = parent.and_then(|p| states.get(p).ok()).map(|(p, v)| (p.current, v.0)).unwrap_or_default();
+        if let Ok((mut sim_position, mut sim_velocity)) = states.get_mut(entity) {
+            sim_position.current = parent_position + position;
+            sim_velocity.0 = parent_velocity + velocity;
+            orbit.written = Some((sim_position.current, sim_velocity.0));
+        }
+    }
+}
+
+fn depth(entity: Entity, parents: &Query<&BodyParent>) -> usize {
+    let mut depth = 0;
+    let mut current = entity;
+    while let Ok(parent) = parents.get(current) {
+        depth += 1;
+        current = parent.0;
+        if current == entity {
+            break;
+        }
+    }
+    depth
+}
+
+//Advances a two body orbit (relative position and velocity) by the given time using universal variables,
+//so elliptic, parabolic and hyperbolic orbits are handled the same way
+pub fn kepler_drift(
+    position: DVec3,
+    velocity: DVec3,
+    mu: f64,
+    timestep: f64,
+) -> (DVec3, DVec3) {
+    let r0 = position.length();
+    if mu <= 0.0 || r0 == 0.0 {
+        return (position + velocity * timestep, velocity);
+    }
+    let sqrt_mu = mu.sqrt();
+    let radial_velocity = position.dot(velocity) / r0;
+    let alpha = 2.0 / r0 - velocity.length_squared() / mu; //reciprocal of the semi major axis
+
+    //full revolutions of bound orbits don't change the state
+    let mut timestep = timestep;
+    if alpha > 0.0 {
+        let period = 2.0 * PI / (sqrt_mu * alpha.powf(1.5));
+        timestep %= period;
+    }
+
+    let mut chi = sqrt_mu * alpha.abs() * timestep;
+    if alpha <= 0.0 || chi == 0.0 {
+        chi = sqrt_mu * timestep / r0;
+    }
+    for _ in 0..MAX_ITERATIONS {
+        let z = alpha * chi * chi;
+        let (c, s) = stumpff(z);
+        let f = r0 * radial_velocity / sqrt_mu * chi * chi * c + (1.0 - alpha * r0) * chi.powi(3) * s + r0 * chi - sqrt_mu * timestep;
+        let derivative = r0 * radial_velocity / sqrt_mu * chi * (1.0 - z * s) + (1.0 - alpha * r0) * chi * chi * c + r0;
+        let ratio = f / derivative;
+        chi -= ratio;
+        if ratio.abs() < TOLERANCE * f64::max(chi.abs(), 1.0) {
+            break;
+        }
+    }
+
+    let z = alpha * chi * chi;
+    let (c, s) = stumpff(z);
+    let f = 1.0 - chi * chi / r0 * c;
+    let g = timestep - chi.powi(3) / sqrt_mu * s;
+    let new_position = f * position + g * velocity;
+    let r = new_position.length();
+    let f_dot = sqrt_mu / (r * r0) * (z * s - 1.0) * chi;
+    let g_dot = 1.0 - chi * chi / r * c;
+    (new_position, f_dot * position + g_dot * velocity)
+}
+
+//Stumpff functions C(z) and S(z)
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1e-6 {
+        (0.5 - z / 24.0 + z * z / 720.0, 1.0 / 6.0 - z / 120.0 + z * z / 5040.0)
+    } else if z > 0.0 {
+        let sqrt_z = z.sqrt();
+        ((1.0 - sqrt_z.cos()) / z, (sqrt_z - sqrt_z.sin()) / sqrt_z.powi(3))
+    } else {
+        let sqrt_z = (-z).sqrt();
+        ((sqrt_z.cosh() - 1.0) / -z, (sqrt_z.sinh() - sqrt_z) / sqrt_z.powi(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+
+    use crate::simulation::components::orbital_elements::{true_anomaly_from_mean, OrbitalElements};
+
+    use super::kepler_drift;
+
+    const MU: f64 = 3.986e14; //Earth
+
+    //The drifted state against the one from advancing the mean anomaly and solving Kepler's equation
+    fn assert_matches_closed_form(elements: OrbitalElements, time: f64) {
+        let (position, velocity) = elements.to_state(MU).unwrap();
+        let (drifted_position, drifted_velocity) = kepler_drift(position, velocity, MU, time);
+        let start = OrbitalElements::from_state(position, velocity, MU).unwrap();
+        let end = OrbitalElements {
+            true_anomaly: true_anomaly_from_mean(start.mean_anomaly + start.mean_motion(MU).unwrap() * time, start.eccentricity),
+            ..start
+        };
+        let (expected_position, expected_velocity) = end.to_state(MU).unwrap();
+        assert!(drifted_position.distance(expected_position) < 1e-9 * expected_position.length(), "{} instead of {}", drifted_position, expected_position);
+        assert!(drifted_velocity.distance(expected_velocity) < 1e-9 * expected_velocity.length(), "{} instead of {}", drifted_velocity, expected_velocity);
+    }
+
+    #[test]
+    fn elliptic_drift() {
+        let elements = OrbitalElements {
+            semi_major_axis: 1e7,
+            eccentricity: 0.3,
+            inclination: 0.5,
+            ascending_node: 1.0,
+            argument_of_periapsis: 2.0,
+            true_anomaly: 0.4,
+            ..Default::default()
+        };
+        let period = 2.0 * PI * (elements.semi_major_axis.powi(3) / MU).sqrt();
+        assert_matches_closed_form(elements, period);
+        assert_matches_closed_form(elements, 1.37 * period);
+    }
+
+    #[test]
+    fn hyperbolic_drift() {
+        let elements = OrbitalElements {
+            semi_major_axis: -2e7,
+            eccentricity: 1.8,
+            inclination: 2.0,
+            ascending_node: 4.0,
+            argument_of_periapsis: 0.3,
+            true_anomaly: (-0.5f64).rem_euclid(TAU),
+            ..Default::default()
+        };
+        assert_matches_closed_form(elements, 5000.0);
+        assert_matches_closed_form(elements, 20000.0);
+    }
+}
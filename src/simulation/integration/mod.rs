@@ -1,31 +1,63 @@
-use crate::constants::DEFAULT_SUB_STEPS;
+use crate::constants::{DEFAULT_SUB_STEPS, PHYSICS_TICK_RATE};
 use crate::simulation::components::body::{Acceleration, Mass, OrbitSettings, SimPosition, Velocity};
 use crate::simulation::components::motion_line::OrbitOffset;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::selection::SelectedEntity;
+use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::conservation::ConservationPlugin;
+use crate::simulation::integration::dormand_prince::DormandPrinceIntegrationPlugin;
 use crate::simulation::integration::euler::EulerIntegrationPlugin;
+use crate::simulation::integration::kepler::{propagate_kepler_orbits, KeplerOrbitPlugin};
+use crate::simulation::integration::runge_kutta::RungeKuttaIntegrationPlugin;
+use crate::simulation::integration::symplectic::SymplecticIntegrationPlugin;
 use crate::simulation::integration::verlet::VerletIntegrationPlugin;
+use crate::simulation::integration::wisdom_holman::WisdomHolmanIntegrationPlugin;
+use crate::simulation::ui::toast::{important_error_toast, ToastContainer};
+use crate::simulation::ui::SimTime;
 use crate::utils::sim_state_type_simulation;
 use bevy::app::App;
 use bevy::diagnostic::{Diagnostic, DiagnosticPath, RegisterDiagnostic};
 use bevy::math::{DVec3, Vec3};
-use bevy::prelude::{not, AppExtStates, Entity, IntoScheduleConfigs, Plugin, Query, Res, ResMut, Resource, States, SystemSet, Transform, Update};
+use bevy::platform::collections::HashMap;
+use bevy::prelude::{not, AppExtStates, Entity, Fixed, FixedUpdate, IntoScheduleConfigs, Name, Plugin, Query, Res, ResMut, Resource, States, SystemSet, Time, Transform, Update};
 
 mod euler;
 mod verlet;
+mod runge_kutta;
+mod dormand_prince;
+mod symplectic;
+mod wisdom_holman;
+pub mod kepler;
+pub mod acceleration;
+pub mod perturbation;
+pub mod conservation;
+pub mod ephemeris;
+mod barnes_hut;
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SimulationStep;
 
+//Runs in FixedUpdate, so every physics step covers the same amount of simulated time regardless of the frame rate
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsStep;
+
 pub const NBODY_STEP_TIME: DiagnosticPath = DiagnosticPath::const_new("nbody_step_time");
 pub const NBODY_TOTAL_TIME: DiagnosticPath = DiagnosticPath::const_new("nbody_total_time");
 pub const NBODY_STEPS: DiagnosticPath = DiagnosticPath::const_new("nbody_steps");
+pub const NBODY_ACCEPTED_STEPS: DiagnosticPath = DiagnosticPath::const_new("nbody_accepted_steps");
+pub const NBODY_REJECTED_STEPS: DiagnosticPath = DiagnosticPath::const_new("nbody_rejected_steps");
 
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum IntegrationType {
     #[default]
     Verlet,
-    Euler
+    Euler,
+    RungeKutta4,
+    DormandPrince,
+    Yoshida4,
+    Yoshida6,
+    ForestRuth,
+    WisdomHolman
 }
 
 impl IntegrationType {
@@ -33,19 +65,79 @@ impl IntegrationType {
     pub fn as_str(&self) -> String {
         match self {
             IntegrationType::Verlet => "Verlet".to_string(),
-            IntegrationType::Euler => "Euler".to_string()
+            IntegrationType::Euler => "Euler".to_string(),
+            IntegrationType::RungeKutta4 => "Runge-Kutta 4".to_string(),
+            IntegrationType::DormandPrince => "Dormand-Prince (Adaptive)".to_string(),
+            IntegrationType::Yoshida4 => "Yoshida 4th Order".to_string(),
+            IntegrationType::Yoshida6 => "Yoshida 6th Order".to_string(),
+            IntegrationType::ForestRuth => "Forest-Ruth (PEFRL)".to_string(),
+            IntegrationType::WisdomHolman => "Wisdom-Holman".to_string()
         }
     }
 
     pub fn all() -> Vec<IntegrationType> {
-        vec![IntegrationType::Verlet, IntegrationType::Euler]
+        vec![IntegrationType::Verlet, IntegrationType::Euler, IntegrationType::RungeKutta4, IntegrationType::DormandPrince, IntegrationType::Yoshida4, IntegrationType::Yoshida6, IntegrationType::ForestRuth, IntegrationType::WisdomHolman]
     }
 
 }
 
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForceModel {
+    #[default]
+    Direct,
+    BarnesHut
+}
+
+impl ForceModel {
+
+    pub fn as_str(&self) -> String {
+        match self {
+            ForceModel::Direct => "Direct".to_string(),
+            ForceModel::BarnesHut => "Barnes-Hut".to_string()
+        }
+    }
+
+    pub fn all() -> Vec<ForceModel> {
+        vec![ForceModel::Direct, ForceModel::BarnesHut]
+    }
+
+}
+
+#[derive(Resource, Clone)]
+pub struct ForceSettings {
+
+    pub model: ForceModel,
+    pub theta: f64, //opening angle of the Barnes-Hut tree, 0 is the same as the direct sum
+    pub exact_mass_ratio: f64, //bodies with at least this fraction of the total mass are never approximated by the tree
+    pub softening: f64, //in meters, set by the scenario
+    pub relativity: bool, //first order post-Newtonian correction around the most massive body, set by the scenario
+
+}
+
+impl Default for ForceSettings {
+    fn default() -> Self {
+        ForceSettings {
+            model: ForceModel::Direct,
+            theta: 0.5,
+            exact_mass_ratio: 1e-10,
+            softening: 0.0,
+            relativity: false,
+        }
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct Pause(pub bool);
 
+//Velocities at the start of the physics step, the positions keep theirs in SimPosition
+#[derive(Resource, Default)]
+struct PreviousVelocities(HashMap<Entity, DVec3>);
+
+//Simulated seconds the current physics step covers. Set from the speed and the sub steps before the step,
+//an integrator that can't cover all of it lowers it, so the sim time never runs ahead of the bodies
+#[derive(Resource, Default)]
+pub struct StepSpan(pub f64);
+
 #[derive(Resource)]
 pub struct SubSteps(pub i32);
 
@@ -75,28 +167,134 @@ impl SubSteps {
 
 }
 
+#[derive(Resource)]
+pub struct AdaptiveTolerance {
+
+    pub relative: f64,
+    pub absolute: f64,
+
+}
+
+impl Default for AdaptiveTolerance {
+    fn default() -> Self {
+        AdaptiveTolerance {
+            relative: 1e-9,
+            absolute: 1e-3,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct AdaptiveStepStats {
+
+    pub accepted: u64,
+    pub rejected: u64,
+    pub step: f64, //current internal step in seconds, 0 until the first frame
+
+}
+
 pub struct IntegrationPlugin;
 
 impl Plugin for IntegrationPlugin {
     fn build(&self, app: &mut App) {
         app
             .init_state::<IntegrationType>()
+            .insert_resource(Time::<Fixed>::from_hz(PHYSICS_TICK_RATE))
             .init_resource::<Pause>()
+            .init_resource::<StepSpan>()
+            .init_resource::<PreviousVelocities>()
             .init_resource::<SubSteps>()
+            .init_resource::<AdaptiveTolerance>()
+            .init_resource::<AdaptiveStepStats>()
+            .init_resource::<ForceSettings>()
             .register_type::<Velocity>()
             .register_type::<Acceleration>()
             .register_type::<Mass>()
             .register_type::<OrbitSettings>()
             .add_plugins(EulerIntegrationPlugin)
             .add_plugins(VerletIntegrationPlugin)
+            .add_plugins(RungeKuttaIntegrationPlugin)
+            .add_plugins(DormandPrinceIntegrationPlugin)
+            .add_plugins(SymplecticIntegrationPlugin)
+            .add_plugins(WisdomHolmanIntegrationPlugin)
+            .add_plugins(KeplerOrbitPlugin)
+            .add_plugins(ConservationPlugin)
             .register_diagnostic(Diagnostic::new(NBODY_STEP_TIME).with_max_history_length(50))
             .register_diagnostic(Diagnostic::new(NBODY_TOTAL_TIME).with_max_history_length(50))
             .register_diagnostic(Diagnostic::new(NBODY_STEPS).with_max_history_length(50))
+            .register_diagnostic(Diagnostic::new(NBODY_ACCEPTED_STEPS).with_max_history_length(50))
+            .register_diagnostic(Diagnostic::new(NBODY_REJECTED_STEPS).with_max_history_length(50))
+            .add_systems(FixedUpdate, (save_previous_positions, set_step_span).before(PhysicsStep).run_if(sim_state_type_simulation).run_if(not(paused)))
+            .add_systems(FixedUpdate, (check_finite_state).after(PhysicsStep).after(propagate_kepler_orbits).before(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)))
+            .add_systems(FixedUpdate, (advance_sim_time).after(PhysicsStep).run_if(sim_state_type_simulation).run_if(not(paused)))
             .add_systems(Update, (change_selection_without_update).in_set(SimulationStep).run_if(sim_state_type_simulation).run_if(paused))
             .add_systems(Update, (update_positions_after_pos_update).in_set(SimulationStep).run_if(sim_state_type_simulation).run_if(not(paused)));
     }
 }
 
+fn save_previous_positions(
+    mut query: Query<(Entity, &mut SimPosition, &Velocity)>,
+    mut previous_velocities: ResMut<PreviousVelocities>,
+) {
+    previous_velocities.0.clear();
+    for (entity, mut sim_pos, velocity) in query.iter_mut() {
+        sim_pos.previous = sim_pos.current;
+        previous_velocities.0.insert(entity, velocity.0);
+    }
+}
+
+pub fn set_step_span(
+    time: Res<Time>,
+    speed: Res<Speed>,
+    sub_steps: Res<SubSteps>,
+    mut span: ResMut<StepSpan>,
+) {
+    span.0 = time.delta_secs_f64() * speed.0 * sub_steps.0 as f64;
+}
+
+//A close encounter can produce infinite or NaN values which would spread to every body with the next step,
+//so we stop the simulation, go back to the state before the step and report the two bodies that were closest before it
+fn check_finite_state(
+    mut query: Query<(Entity, &Name, &mut SimPosition, &mut Velocity)>,
+    previous_velocities: Res<PreviousVelocities>,
+    mut pause: ResMut<Pause>,
+    mut toasts: ResMut<ToastContainer>,
+) {
+    let Some(name) = query.iter().find(|(_, _, pos, vel)| !pos.current.is_finite() || !vel.0.is_finite()).map(|(_, name, _, _)| name.clone()) else {
+        return;
+    };
+    let bodies: Vec<(&Name, DVec3)> = query.iter().map(|(_, name, pos, _)| (name, pos.previous)).collect();
+    let mut closest: Option<(&Name, &Name, f64)> = None;
+    for (index, (first, first_position)) in bodies.iter().enumerate() {
+        for (second, second_position) in &bodies[index + 1..] {
+            let distance = first_position.distance(*second_position);
+            if closest.is_none_or(|(_, _, min)| distance < min) {
+                closest = Some((*first, *second, distance));
+            }
+        }
+    }
+    let message = match closest {
+        Some((first, second, distance)) => format!("Simulation paused: {} produced a non-finite state, the closest bodies before the step were {} and {} ({:.3} km)", name, first, second, distance / 1000.0),
+        None => format!("Simulation paused: {} produced a non-finite state", name)
+    };
+    toasts.0.add(important_error_toast(message.as_str()));
+    pause.0 = true;
+    //go back to the state before the step, so resuming doesn't continue from a broken state
+    for (entity, _, mut pos, mut vel) in query.iter_mut() {
+        pos.current = pos.previous;
+        if let Some(velocity) = previous_velocities.0.get(&entity) {
+            vel.0 = *velocity;
+        }
+    }
+}
+
+pub fn advance_sim_time(
+    span: Res<StepSpan>,
+    mut sim_time: ResMut<SimTime>,
+) {
+    sim_time.0 += span.0;
+}
+
 fn change_selection_without_update(
     mut query: Query<(Entity, &mut SimPosition, &mut Transform)>,
     selected_entity: Res<SelectedEntity>,
@@ -134,15 +332,17 @@ fn update_positions_after_pos_update(
     mut query: Query<(Entity, &mut Acceleration, &mut OrbitSettings, &mut SimPosition, &mut Transform)>,
     mut orbit_offset: ResMut<OrbitOffset>,
     selected_entity: Res<SelectedEntity>,
-    scale: Res<SimulationScale>
+    scale: Res<SimulationScale>,
+    fixed_time: Res<Time<Fixed>>,
 ) {
+    let fraction = fixed_time.overstep_fraction_f64(); //how far we are between the last and the next physics step
     let offset = match selected_entity.entity { //if orbit_offset.enabled is true, we calculate the new position of the selected entity first and then move it to 0,0,0 and add the actual position to all other bodies
         Some(selected) => {
             if let Ok((_, acc, mut orbit_s, sim_pos, mut transform)) = query.get_mut(selected) {
                 if orbit_s.display_force {
                     orbit_s.force_direction = acc.0.normalize();
                 }
-                let raw_translation = scale.m_to_unit_dvec(sim_pos.current);
+                let raw_translation = scale.m_to_unit_dvec(sim_pos.interpolated(fraction));
                 transform.translation = Vec3::ZERO; //the selected entity will always be at 0,0,0
                 -raw_translation
             } else {
@@ -160,7 +360,7 @@ fn update_positions_after_pos_update(
         if orbit_s.display_force {
             orbit_s.force_direction = acc.0.normalize();
         }
-        let pos_without_offset = scale.m_to_unit_dvec(sim_pos.current);
+        let pos_without_offset = scale.m_to_unit_dvec(sim_pos.interpolated(fraction));
         transform.translation = (pos_without_offset + offset).as_vec3(); //apply offset
     }
     orbit_offset.value = offset.as_vec3();
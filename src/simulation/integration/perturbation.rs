@@ -0,0 +1,323 @@
+use std::f64::consts::PI;
+
+use bevy::ecs::system::SystemParam;
+use bevy::math::{DMat3, DVec3};
+use bevy::prelude::{Entity, Query, Res};
+
+use crate::constants::{DRAG_COEFFICIENT, G, LUMINOUS_EFFICACY, SPEED_OF_LIGHT};
+use crate::simulation::components::atmosphere::{altitude, surface_radii};
+use crate::simulation::components::body::{Atmosphere, BodyParent, BodyRotation, BodyShape, LightSource, Mass, RadiationPressure, RotationSpeed, ZonalHarmonics};
+use crate::simulation::integration::ForceSettings;
+
+//Queries every body component that adds forces on top of the point mass gravity
+#[derive(SystemParam)]
+pub struct PerturbationSources<'w, 's> {
+    harmonics: Query<'w, 's, (&'static ZonalHarmonics, &'static BodyShape, &'static BodyRotation)>,
+    radiation: Query<'w, 's, (&'static RadiationPressure, &'static Mass)>,
+    lights: Query<'w, 's, &'static LightSource>,
+    atmospheres: Query<'w, 's, (&'static Atmosphere, &'static BodyShape, &'static BodyRotation, &'static RotationSpeed)>,
+    children: Query<'w, 's, (&'static BodyParent, &'static BodyShape, &'static Mass, Option<&'static RadiationPressure>)>,
+    settings: Res<'w, ForceSettings>,
+}
+
+impl PerturbationSources<'_, '_> {
+
+    //The entities have to be in the same order as the positions the perturbations are applied to later
+    pub fn collect(&self, entities: impl Iterator<Item = Entity>) -> Perturbations {
+        let mut perturbations = Perturbations {
+            relativity: self.settings.relativity,
+            ..Perturbations::default()
+        };
+        let entities: Vec<Entity> = entities.collect();
+        for light in self.lights.iter().filter(|light| light.enabled && light.intensity > 0.0) {
+            if let Some(index) = entities.iter().position(|entity| *entity == light.parent) {
+                perturbations.light_sources.push((index, light.intensity as f64 / LUMINOUS_EFFICACY));
+            }
+        }
+        for (index, entity) in entities.iter().enumerate() {
+            if let Ok((atmosphere, shape, rotation, rotation_speed)) = self.atmospheres.get(*entity) {
+                if !atmosphere.is_zero() {
+                    //the rotation speed is in minutes per rotation
+                    let spin = if rotation_speed.0 > 0.0 { 2.0 * PI / (rotation_speed.0 * 60.0) } else { 0.0 };
+                    perturbations.atmospheres.push(AtmosphereBody {
+                        index,
+                        atmosphere: *atmosphere,
+                        to_body_frame: rotation.matrix.as_dmat3().transpose(),
+                        radii: surface_radii(shape),
+                        spin: rotation.matrix.z_axis.as_dvec3().normalize_or(DVec3::Z) * spin,
+                    });
+                }
+            }
+        }
+        for (index, entity) in entities.iter().enumerate() {
+            if let Ok((parent, shape, mass, radiation)) = self.children.get(*entity) {
+                let parent_index = perturbations.atmospheres.iter().position(|atmosphere| entities[atmosphere.index] == parent.0);
+                if let Some(atmosphere) = parent_index.filter(|_| mass.0 > 0.0) {
+                    //the cross-sectional area of the radiation pressure, or the shape if there is none
+                    let area = match radiation {
+                        Some(radiation) if radiation.area > 0.0 => radiation.area,
+                        _ => PI * (shape.ellipsoid.mean_equatorial_radius_km() * 1000.0).powi(2),
+                    };
+                    perturbations.dragged_bodies.push((index, atmosphere, DRAG_COEFFICIENT * area / (2.0 * mass.0)));
+                }
+            }
+        }
+        for (index, entity) in entities.into_iter().enumerate() {
+            if let Ok((radiation, mass)) = self.radiation.get(entity) {
+                if !radiation.is_zero() && mass.0 > 0.0 && !perturbations.light_sources.is_empty() {
+                    perturbations.sails.push((index, radiation.reflectivity * radiation.area / mass.0));
+                }
+            }
+            if let Ok((harmonics, shape, rotation)) = self.harmonics.get(entity) {
+                perturbations.oblate_bodies.push(OblateBody {
+                    index,
+                    harmonics: *harmonics,
+                    radius: shape.ellipsoid.semi_major_equatorial_radius_km * 1000.0,
+                    //the rotation matrix turns the body fixed frame into the simulation frame, so its z column is the pole
+                    pole: rotation.matrix.z_axis.as_dvec3().normalize_or(DVec3::Z),
+                });
+            }
+        }
+        perturbations
+    }
+
+}
+
+struct AtmosphereBody {
+    index: usize,
+    atmosphere: Atmosphere,
+    to_body_frame: DMat3,
+    radii: DVec3, //surface ellipsoid in meters
+    spin: DVec3, //angular velocity in rad/s, the atmosphere rotates with the body
+}
+
+struct OblateBody {
+    index: usize,
+    harmonics: ZonalHarmonics,
+    radius: f64, //reference radius of the coefficients in meters
+    pole: DVec3,
+}
+
+//Forces on top of the point mass gravity, collected once per physics step
+#[derive(Default)]
+pub struct Perturbations {
+    oblate_bodies: Vec<OblateBody>,
+    light_sources: Vec<(usize, f64)>, //index and radiated power in W
+    sails: Vec<(usize, f64)>, //index and reflectivity * area / mass
+    atmospheres: Vec<AtmosphereBody>,
+    dragged_bodies: Vec<(usize, usize, f64)>, //index, atmosphere and drag coefficient * area / (2 * mass)
+    relativity: bool,
+}
+
+impl Perturbations {
+
+    pub fn is_empty(&self) -> bool {
+        self.oblate_bodies.is_empty() && self.sails.is_empty() && self.dragged_bodies.is_empty() && !self.relativity
+    }
+
+    pub fn apply(&self, positions: &[DVec3], velocities: &[DVec3], masses: &[f64], accelerations: &mut [DVec3]) {
+        for body in &self.oblate_bodies {
+            let mu = G * masses[body.index];
+            if mu == 0.0 {
+                continue;
+            }
+            //the oblate body is pulled back by everything it pulls on, so the total momentum doesn't change
+            let mut reaction = DVec3::ZERO;
+            for (index, position) in positions.iter().enumerate() {
+                if index == body.index {
+                    continue;
+                }
+                let acceleration = zonal_acceleration(*position - positions[body.index], body, mu);
+                accelerations[index] += acceleration;
+                reaction -= acceleration * masses[index];
+            }
+            accelerations[body.index] += reaction / masses[body.index];
+        }
+        //radiation pressure: a = P * Cr * A / (4π * r² * c * m), pointing away from the light source
+        for (index, ratio) in &self.sails {
+            for (source, power) in &self.light_sources {
+                if index == source {
+                    continue;
+                }
+                let relative = positions[*index] - positions[*source];
+                let r_sq = relative.length_squared();
+                if r_sq == 0.0 {
+                    continue;
+                }
+                accelerations[*index] += relative * (power * ratio / (4.0 * PI * SPEED_OF_LIGHT * r_sq * r_sq.sqrt()));
+            }
+        }
+        //drag: a = -rho * Cd * A / (2 * m) * |v| * v, with v relative to the rotating atmosphere
+        for (index, atmosphere, ballistic) in &self.dragged_bodies {
+            let body = &self.atmospheres[*atmosphere];
+            let relative = positions[*index] - positions[body.index];
+            let density = body.atmosphere.density(altitude(relative, body.to_body_frame, body.radii));
+            if density == 0.0 {
+                continue;
+            }
+            let velocity = velocities[*index] - velocities[body.index] - body.spin.cross(relative);
+            let acceleration = velocity * (-density * ballistic * velocity.length());
+            accelerations[*index] += acceleration;
+            if masses[body.index] > 0.0 {
+                accelerations[body.index] -= acceleration * masses[*index] / masses[body.index];
+            }
+        }
+        if self.relativity {
+            apply_relativity(positions, velocities, masses, accelerations);
+        }
+    }
+
+}
+
+//Acceleration caused by the J2, J3 and J4 terms of the gravity field at a position relative to the oblate body.
+//Gradient of the zonal potential: a = mu / r² * sum(Jn * (R/r)^n * (((n+1) * Pn(u) + u * Pn'(u)) * r̂ - Pn'(u) * pole))
+//with u being the sine of the latitude above the equator
+fn zonal_acceleration(relative: DVec3, body: &OblateBody, mu: f64) -> DVec3 {
+    let r = relative.length();
+    if r == 0.0 {
+        return DVec3::ZERO;
+    }
+    let direction = relative / r;
+    let u = direction.dot(body.pole);
+    let u2 = u * u;
+    let ratio = body.radius / r;
+    let terms = [
+        (2, body.harmonics.j2, (3.0 * u2 - 1.0) / 2.0, 3.0 * u),
+        (3, body.harmonics.j3, (5.0 * u2 - 3.0) * u / 2.0, (15.0 * u2 - 3.0) / 2.0),
+        (4, body.harmonics.j4, (35.0 * u2 * u2 - 30.0 * u2 + 3.0) / 8.0, (35.0 * u2 - 15.0) * u / 2.0),
+    ];
+    let mut radial = 0.0;
+    let mut polar = 0.0;
+    for (n, j, legendre, derivative) in terms {
+        if j == 0.0 {
+            continue;
+        }
+        let factor = j * ratio.powi(n);
+        radial += factor * ((n + 1) as f64 * legendre + u * derivative);
+        polar += factor * derivative;
+    }
+    (direction * radial - body.pole * polar) * (mu / (r * r))
+}
+
+//First order post-Newtonian correction for every body orbiting the most massive one (the star).
+//Test particle limit of the Einstein-Infeld-Hoffmann equations in the frame of the star:
+//a = mu / (c² r³) * ((4 * mu / r - v²) * r + 4 * (r · v) * v)
+//It makes orbits precess, for Mercury by about 43 arcseconds per century.
+fn apply_relativity(positions: &[DVec3], velocities: &[DVec3], masses: &[f64], accelerations: &mut [DVec3]) {
+    let Some(star) = (0..masses.len()).max_by(|a, b| masses[*a].total_cmp(&masses[*b])) else {
+        return;
+    };
+    let mu = G * masses[star];
+    if mu == 0.0 {
+        return;
+    }
+    let mut reaction = DVec3::ZERO;
+    for index in 0..positions.len() {
+        if index == star {
+            continue;
+        }
+        let relative = positions[index] - positions[star];
+        let velocity = velocities[index] - velocities[star];
+        let r = relative.length();
+        if r == 0.0 {
+            continue;
+        }
+        let acceleration = (relative * (4.0 * mu / r - velocity.length_squared()) + velocity * (4.0 * relative.dot(velocity)))
+            * (mu / (SPEED_OF_LIGHT * SPEED_OF_LIGHT * r * r * r));
+        accelerations[index] += acceleration;
+        reaction -= acceleration * masses[index];
+    }
+    accelerations[star] += reaction / masses[star];
+}
+
+#[cfg(test)]
+impl Perturbations {
+
+    pub fn with_relativity(relativity: bool) -> Self {
+        Perturbations {
+            relativity,
+            ..Perturbations::default()
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use bevy::ecs::system::SystemState;
+    use bevy::math::DVec3;
+    use bevy::prelude::{Color, World};
+
+    use crate::constants::{G, LUMINOUS_EFFICACY};
+    use crate::simulation::components::body::{LightSource, Mass, RadiationPressure, ZonalHarmonics};
+    use crate::simulation::integration::ForceSettings;
+
+    use super::{zonal_acceleration, OblateBody, PerturbationSources};
+
+    //A circular orbit inclined by 50° around an Earth with only J2, integrated with RK4 (5 s steps) for 15 orbits.
+    //The node has to regress by the secular rate -3/2 * n * J2 * (R/a)² * cos(i)
+    #[test]
+    fn j2_nodal_regression() {
+        let mu = G * 5.972e24;
+        let body = OblateBody {
+            index: 0,
+            harmonics: ZonalHarmonics { j2: 1.08262668e-3, j3: 0.0, j4: 0.0 },
+            radius: 6_378_137.0,
+            pole: DVec3::Z,
+        };
+        let radius = 7_000_000.0;
+        let inclination = 50f64.to_radians();
+        let mut position = DVec3::new(radius, 0.0, 0.0);
+        let mut velocity = DVec3::new(0.0, inclination.cos(), inclination.sin()) * (mu / radius).sqrt();
+        let acceleration = |p: DVec3| -mu * p / p.length().powi(3) + zonal_acceleration(p, &body, mu);
+
+        let mean_motion = (mu / radius.powi(3)).sqrt();
+        let duration = 15.0 * 2.0 * PI / mean_motion;
+        let steps = (duration / 5.0).round() as usize;
+        let timestep = duration / steps as f64;
+        for _ in 0..steps {
+            let (k1x, k1v) = (velocity, acceleration(position));
+            let (k2x, k2v) = (velocity + k1v * timestep / 2.0, acceleration(position + k1x * timestep / 2.0));
+            let (k3x, k3v) = (velocity + k2v * timestep / 2.0, acceleration(position + k2x * timestep / 2.0));
+            let (k4x, k4v) = (velocity + k3v * timestep, acceleration(position + k3x * timestep));
+            position += (k1x + 2.0 * k2x + 2.0 * k3x + k4x) * timestep / 6.0;
+            velocity += (k1v + 2.0 * k2v + 2.0 * k3v + k4v) * timestep / 6.0;
+        }
+
+        let node = DVec3::Z.cross(position.cross(velocity));
+        let regression = node.y.atan2(node.x);
+        let expected = -1.5 * mean_motion * body.harmonics.j2 * (body.radius / radius).powi(2) * inclination.cos() * duration;
+        assert!((regression / expected - 1.0).abs() < 0.01, "node moved by {} rad instead of {} rad", regression, expected);
+    }
+
+    //A sail with Cr = 1.8, 100 m² and 500 kg at 1 AU from a light as bright as the Sun (3.828e26 W * 98 lm/W).
+    //That is 3.828e26 / (4π * (1.496e11 m)²) = 1361 W/m², a pressure of 1361 / c = 4.540e-6 N/m²,
+    //which pushes the sail away from the light with 4.540e-6 * 1.8 * 100 / 500 = 1.6345e-6 m/s²
+    #[test]
+    fn radiation_pressure_at_one_au() {
+        let mut world = World::new();
+        world.insert_resource(ForceSettings::default());
+        let sun = world.spawn(Mass(1.989e30)).id();
+        world.spawn(LightSource {
+            parent: sun,
+            color: Color::WHITE,
+            imposter_color: Color::WHITE,
+            intensity: (3.828e26 * LUMINOUS_EFFICACY) as f32,
+            range: 0.0,
+            enabled: true,
+        });
+        let sail = world.spawn((Mass(500.0), RadiationPressure { area: 100.0, reflectivity: 1.8 })).id();
+        let mut sources: SystemState<PerturbationSources> = SystemState::new(&mut world);
+        let perturbations = sources.get(&world).collect([sun, sail].into_iter());
+
+        let positions = [DVec3::ZERO, DVec3::new(0.0, 1.495978707e11, 0.0)];
+        let mut accelerations = [DVec3::ZERO; 2];
+        perturbations.apply(&positions, &[DVec3::ZERO; 2], &[1.989e30, 500.0], &mut accelerations);
+        let expected = 1.6345305e-6;
+        assert_eq!(accelerations[0], DVec3::ZERO);
+        assert!(accelerations[1].x == 0.0 && accelerations[1].z == 0.0, "pushed along {}", accelerations[1]);
+        assert!((accelerations[1].y / expected - 1.0).abs() < 1e-6, "{} m/s² instead of {} m/s²", accelerations[1].y, expected);
+    }
+}
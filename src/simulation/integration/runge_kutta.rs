@@ -0,0 +1,117 @@
+use std::time::Instant;
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::diagnostic::Diagnostics;
+use bevy::math::DVec3;
+use bevy::prelude::{in_state, not, Entity, IntoScheduleConfigs, Query, Res, Time, Transform, With, Without};
+
+use crate::simulation::components::body::{Acceleration, KeplerOrbit, Mass, OrbitSettings, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::acceleration::{calculate_accelerations, gravitational_mass};
+use crate::simulation::integration::ephemeris::{Ephemerides, EphemerisSources};
+use crate::simulation::integration::perturbation::{PerturbationSources, Perturbations};
+use crate::simulation::integration::{paused, ForceSettings, IntegrationType, PhysicsStep, SubSteps, NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
+use crate::utils::sim_state_type_simulation;
+
+pub struct RungeKuttaIntegrationPlugin;
+
+impl Plugin for RungeKuttaIntegrationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (apply_physics).in_set(PhysicsStep).run_if(sim_state_type_simulation).run_if(in_state(IntegrationType::RungeKutta4)).run_if(not(paused)));
+    }
+}
+
+fn apply_physics(
+    mut query: Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    time: Res<Time>,
+    speed: Res<Speed>,
+    sub_steps: Res<SubSteps>,
+    forces: Res<ForceSettings>,
+    test_particles: Query<(), With<TestParticle>>,
+    perturbation_sources: PerturbationSources,
+    ephemeris_sources: EphemerisSources,
+    mut diagnostics: Diagnostics,
+) {
+    let start = Instant::now();
+    let delta = time.delta_secs_f64();
+    let timestep = delta * speed.0;
+    //the intermediate stages need positions that are never written to the bodies, so we work on plain arrays
+    let perturbations = perturbation_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let mut ephemerides = ephemeris_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let masses: Vec<f64> = query.iter().map(|(entity, mass, _, _, _, _, _)| gravitational_mass(entity, mass, &test_particles)).collect();
+    let mut positions: Vec<DVec3> = query.iter().map(|(_, _, _, _, _, pos, _)| pos.current).collect();
+    let mut velocities: Vec<DVec3> = query.iter().map(|(_, _, _, _, vel, _, _)| vel.0).collect();
+    for _ in 0..sub_steps.0-1 {
+        step(&masses, &mut positions, &mut velocities, timestep, &forces, &perturbations, &mut ephemerides);
+    }
+    let start_step = Instant::now();
+    step(&masses, &mut positions, &mut velocities, timestep, &forces, &perturbations, &mut ephemerides);
+    diagnostics.add_measurement(&NBODY_STEP_TIME, || start_step.elapsed().as_nanos() as f64);
+    let accelerations = calculate_accelerations(&positions, &velocities, &masses, &forces, &perturbations);
+    for (index, (_, _, mut acc, _, mut vel, mut pos, _)) in query.iter_mut().enumerate() {
+        acc.0 = accelerations[index];
+        vel.0 = velocities[index];
+        pos.current = positions[index];
+    }
+    diagnostics.add_measurement(&NBODY_TOTAL_TIME, || start.elapsed().as_nanos() as f64);
+    diagnostics.add_measurement(&NBODY_STEPS, || sub_steps.0 as f64 / delta);
+}
+
+fn step(
+    masses: &[f64],
+    positions: &mut [DVec3],
+    velocities: &mut [DVec3],
+    timestep: f64,
+    forces: &ForceSettings,
+    perturbations: &Perturbations,
+    ephemerides: &mut Ephemerides,
+) {
+    let half_step = timestep / 2.0;
+    ephemerides.apply(0.0, positions, velocities);
+    let k1_vel = velocities.to_vec();
+    let k1_acc = calculate_accelerations(positions, velocities, masses, forces, perturbations);
+
+    //every stage sees the ephemeris bodies where they are at the time of the stage
+    let (k2_pos, k2_vel) = stage(positions, velocities, &k1_vel, &k1_acc, half_step, ephemerides);
+    let k2_acc = calculate_accelerations(&k2_pos, &k2_vel, masses, forces, perturbations);
+
+    let (k3_pos, k3_vel) = stage(positions, velocities, &k2_vel, &k2_acc, half_step, ephemerides);
+    let k3_acc = calculate_accelerations(&k3_pos, &k3_vel, masses, forces, perturbations);
+
+    let (k4_pos, k4_vel) = stage(positions, velocities, &k3_vel, &k3_acc, timestep, ephemerides);
+    let k4_acc = calculate_accelerations(&k4_pos, &k4_vel, masses, forces, perturbations);
+
+    for index in 0..positions.len() {
+        if ephemerides.contains(index) {
+            continue;
+        }
+        positions[index] += timestep / 6.0 * (k1_vel[index] + 2.0 * k2_vel[index] + 2.0 * k3_vel[index] + k4_vel[index]);
+        velocities[index] += timestep / 6.0 * (k1_acc[index] + 2.0 * k2_acc[index] + 2.0 * k3_acc[index] + k4_acc[index]);
+    }
+    ephemerides.advance(timestep);
+    ephemerides.apply(0.0, positions, velocities);
+}
+
+fn stage(
+    positions: &[DVec3],
+    velocities: &[DVec3],
+    position_derivatives: &[DVec3],
+    velocity_derivatives: &[DVec3],
+    timestep: f64,
+    ephemerides: &Ephemerides,
+) -> (Vec<DVec3>, Vec<DVec3>) {
+    let mut stage_positions = offset(positions, position_derivatives, timestep);
+    let mut stage_velocities = offset(velocities, velocity_derivatives, timestep);
+    ephemerides.apply(timestep, &mut stage_positions, &mut stage_velocities);
+    (stage_positions, stage_velocities)
+}
+
+fn offset(
+    values: &[DVec3],
+    derivatives: &[DVec3],
+    timestep: f64,
+) -> Vec<DVec3> {
+    values.iter().zip(derivatives).map(|(value, derivative)| *value + *derivative * timestep).collect()
+}
+
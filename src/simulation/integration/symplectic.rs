@@ -0,0 +1,204 @@
+use std::time::Instant;
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::diagnostic::Diagnostics;
+use bevy::prelude::{not, Entity, IntoScheduleConfigs, Query, Res, State, Time, Transform, With, Without};
+
+use crate::simulation::components::body::{Acceleration, KeplerOrbit, Mass, OrbitSettings, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::acceleration::{follow_ephemerides, update_acceleration};
+use crate::simulation::integration::ephemeris::{Ephemerides, EphemerisSources};
+use crate::simulation::integration::perturbation::{PerturbationSources, Perturbations};
+use crate::simulation::integration::{paused, ForceSettings, IntegrationType, PhysicsStep, SubSteps, NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
+use crate::utils::sim_state_type_simulation;
+
+//Yoshida (1990) triple jump weights for the 4th order scheme
+const YOSHIDA4_W1: f64 = 1.3512071919596578; // 1 / (2 - 2^(1/3))
+const YOSHIDA4_W0: f64 = -1.7024143839193153; // -2^(1/3) / (2 - 2^(1/3))
+
+//Yoshida (1990) solution A for the 6th order scheme
+const YOSHIDA6_W1: f64 = -1.17767998417887;
+const YOSHIDA6_W2: f64 = 0.235573213359357;
+const YOSHIDA6_W3: f64 = 0.784513610477560;
+const YOSHIDA6_W0: f64 = 1.0 - 2.0 * (YOSHIDA6_W1 + YOSHIDA6_W2 + YOSHIDA6_W3);
+
+//Omelyan, Mryglod & Folk (2002) position extended Forest-Ruth like coefficients
+const PEFRL_XI: f64 = 0.1786178958448091;
+const PEFRL_LAMBDA: f64 = -0.2123418310626054;
+const PEFRL_CHI: f64 = -0.06626458266981849;
+
+pub struct SymplecticIntegrationPlugin;
+
+impl Plugin for SymplecticIntegrationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (apply_physics).in_set(PhysicsStep).run_if(sim_state_type_simulation).run_if(symplectic_integrator).run_if(not(paused)));
+    }
+}
+
+//A scheme is a sequence of drift (position) and kick (velocity) coefficients, applied alternately starting with a drift
+struct Scheme {
+    drift: Vec<f64>,
+    kick: Vec<f64>,
+}
+
+impl Scheme {
+
+    fn from_type(integrator: &IntegrationType) -> Option<Self> {
+        match integrator {
+            IntegrationType::Yoshida4 => Some(Self::composition(&[YOSHIDA4_W1, YOSHIDA4_W0, YOSHIDA4_W1])),
+            IntegrationType::Yoshida6 => Some(Self::composition(&[YOSHIDA6_W3, YOSHIDA6_W2, YOSHIDA6_W1, YOSHIDA6_W0, YOSHIDA6_W1, YOSHIDA6_W2, YOSHIDA6_W3])),
+            IntegrationType::ForestRuth => Some(Scheme {
+                drift: vec![PEFRL_XI, PEFRL_CHI, 1.0 - 2.0 * (PEFRL_CHI + PEFRL_XI), PEFRL_CHI, PEFRL_XI],
+                kick: vec![(1.0 - 2.0 * PEFRL_LAMBDA) / 2.0, PEFRL_LAMBDA, PEFRL_LAMBDA, (1.0 - 2.0 * PEFRL_LAMBDA) / 2.0, 0.0],
+            }),
+            _ => None
+        }
+    }
+
+    //Chains drift-kick-drift leapfrog steps with the given weights, merging the drifts between them
+    fn composition(weights: &[f64]) -> Self {
+        let mut drift = vec![weights[0] / 2.0];
+        let mut kick = Vec::with_capacity(weights.len() + 1);
+        for (index, weight) in weights.iter().enumerate() {
+            kick.push(*weight);
+            drift.push((weight + weights.get(index + 1).unwrap_or(&0.0)) / 2.0);
+        }
+        kick.push(0.0);
+        Scheme { drift, kick }
+    }
+
+}
+
+fn symplectic_integrator(
+    integrator: Res<State<IntegrationType>>
+) -> bool {
+    Scheme::from_type(integrator.get()).is_some()
+}
+
+fn apply_physics(
+    mut query: Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    time: Res<Time>,
+    speed: Res<Speed>,
+    sub_steps: Res<SubSteps>,
+    forces: Res<ForceSettings>,
+    test_particles: Query<(), With<TestParticle>>,
+    perturbation_sources: PerturbationSources,
+    ephemeris_sources: EphemerisSources,
+    integrator: Res<State<IntegrationType>>,
+    mut diagnostics: Diagnostics,
+) {
+    let Some(scheme) = Scheme::from_type(integrator.get()) else {
+        return;
+    };
+    let start = Instant::now();
+    let delta = time.delta_secs_f64();
+    let timestep = delta * speed.0;
+    let perturbations = perturbation_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let mut ephemerides = ephemeris_sources.collect(query.iter().map(|(entity, ..)| entity));
+    for _ in 0..sub_steps.0-1 {
+        step(&mut query, timestep, &scheme, &test_particles, &forces, &perturbations, &mut ephemerides);
+    }
+    let start_step = Instant::now();
+    step(&mut query, timestep, &scheme, &test_particles, &forces, &perturbations, &mut ephemerides);
+    follow_ephemerides(&mut query, &ephemerides);
+    diagnostics.add_measurement(&NBODY_STEP_TIME, || start_step.elapsed().as_nanos() as f64);
+    diagnostics.add_measurement(&NBODY_TOTAL_TIME, || start.elapsed().as_nanos() as f64);
+    diagnostics.add_measurement(&NBODY_STEPS, || sub_steps.0 as f64 / delta);
+}
+
+fn step(
+    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    timestep: f64,
+    scheme: &Scheme,
+    test_particles: &Query<(), With<TestParticle>>,
+    forces: &ForceSettings,
+    perturbations: &Perturbations,
+    ephemerides: &mut Ephemerides,
+) {
+    for (drift, kick) in scheme.drift.iter().zip(&scheme.kick) {
+        drift_positions(query, timestep * drift, ephemerides);
+        ephemerides.advance(timestep * drift);
+        if *kick != 0.0 {
+            update_acceleration(query, test_particles, forces, perturbations, ephemerides);
+            kick_velocities(query, timestep * kick, ephemerides);
+        }
+    }
+}
+
+fn drift_positions(
+    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    timestep: f64,
+    ephemerides: &Ephemerides,
+) {
+    for (index, (_, _, _, _, vel, mut pos, _)) in query.iter_mut().enumerate() {
+        if ephemerides.contains(index) {
+            continue;
+        }
+        pos.current += vel.0 * timestep;
+    }
+}
+
+fn kick_velocities(
+    query: &mut Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform), Without<KeplerOrbit>>,
+    timestep: f64,
+    ephemerides: &Ephemerides,
+) {
+    for (index, (_, _, acc, _, mut vel, _, _)) in query.iter_mut().enumerate() {
+        if ephemerides.contains(index) {
+            continue;
+        }
+        vel.0 += acc.0 * timestep;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use crate::constants::G;
+    use crate::simulation::integration::acceleration::calculate_accelerations;
+    use crate::simulation::integration::kepler::kepler_drift;
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::{ForceSettings, IntegrationType};
+
+    use super::Scheme;
+
+    const DAY: f64 = 86400.0;
+
+    //Sun and an eccentric, inclined Earth over 400 days, returns the relative position error against the closed form two body solution
+    fn position_error(integrator: &IntegrationType, steps: usize) -> f64 {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let scheme = Scheme::from_type(integrator).unwrap();
+        let masses = [1.989e30, 5.972e24];
+        let mut positions = vec![DVec3::ZERO, DVec3::new(1.496e11, 0.0, 0.0)];
+        let mut velocities = vec![DVec3::ZERO, DVec3::new(0.0, 32_000.0, 1_000.0)];
+        let forces = ForceSettings::default();
+        let perturbations = Perturbations::default();
+        let (start_position, start_velocity) = (positions[1] - positions[0], velocities[1] - velocities[0]);
+        let timestep = 400.0 * DAY / steps as f64;
+        for _ in 0..steps {
+            for (drift, kick) in scheme.drift.iter().zip(&scheme.kick) {
+                positions = positions.iter().zip(&velocities).map(|(position, velocity)| *position + *velocity * timestep * drift).collect();
+                if *kick != 0.0 {
+                    let accelerations = calculate_accelerations(&positions, &velocities, &masses, &forces, &perturbations);
+                    velocities = velocities.iter().zip(&accelerations).map(|(velocity, acceleration)| *velocity + *acceleration * timestep * kick).collect();
+                }
+            }
+        }
+        let (position, _) = kepler_drift(start_position, start_velocity, G * masses.iter().sum::<f64>(), 400.0 * DAY);
+        (positions[1] - positions[0] - position).length() / position.length()
+    }
+
+    //Halving the step size has to shrink the error by 2 to the power of the order of the scheme
+    #[test]
+    fn converges_with_the_order_of_the_scheme() {
+        for (integrator, order, steps) in [(IntegrationType::Yoshida4, 4.0, 100), (IntegrationType::ForestRuth, 4.0, 100), (IntegrationType::Yoshida6, 6.0, 50)] {
+            let coarse = position_error(&integrator, steps);
+            let fine = position_error(&integrator, steps * 2);
+            let measured = (coarse / fine).log2();
+            assert!((measured - order).abs() < 0.5, "{:?} converges with order {} (errors {} and {})", integrator, measured, coarse, fine);
+        }
+    }
+}
@@ -0,0 +1,574 @@
+use std::time::Instant;
+
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::diagnostic::Diagnostics;
+use bevy::math::DVec3;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::{in_state, not, Entity, IntoScheduleConfigs, Local, Name, NextState, Query, Res, ResMut, State, Time, Transform, With, Without};
+
+use crate::constants::G;
+use crate::simulation::components::body::{Acceleration, BodyParent, EphemerisDriven, KeplerOrbit, Mass, OrbitSettings, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::kepler::kepler_drift;
+use crate::simulation::integration::acceleration::{calculate_accelerations, gravitational_mass};
+use crate::simulation::integration::perturbation::{PerturbationSources, Perturbations};
+use crate::simulation::integration::{paused, ForceSettings, IntegrationType, PhysicsStep, StepSpan, SubSteps, NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
+use crate::simulation::ui::toast::{warning_toast, ToastContainer};
+use crate::utils::sim_state_type_simulation;
+
+pub struct WisdomHolmanIntegrationPlugin;
+
+impl Plugin for WisdomHolmanIntegrationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, (apply_physics).in_set(PhysicsStep).run_if(sim_state_type_simulation).run_if(in_state(IntegrationType::WisdomHolman)).run_if(not(paused)));
+    }
+}
+
+//The parent links decide which coordinates the Keplerian part is solved in:
+//a single star with only planets uses democratic heliocentric coordinates, everything else (moons, multiple stars)
+//uses hierarchical Jacobi coordinates where every body orbits the barycenter of its parent and the inner siblings
+enum Coordinates {
+    DemocraticHeliocentric,
+    Jacobi
+}
+
+struct Hierarchy {
+    roots: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    //bodies ordered so that every parent comes before its children
+    order: Vec<usize>,
+}
+
+impl Hierarchy {
+
+    fn new(parents: &[Option<usize>], positions: &[DVec3]) -> Self {
+        let mut roots = Vec::new();
+        let mut children = vec![Vec::new(); parents.len()];
+        for (index, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(parent) => children[*parent].push(index),
+                None => roots.push(index)
+            }
+        }
+        //inner bodies first, like the sorted children in the scenario files
+        for (parent, siblings) in children.iter_mut().enumerate() {
+            siblings.sort_by(|a, b| {
+                let distance_a = positions[*a].distance_squared(positions[parent]);
+                let distance_b = positions[*b].distance_squared(positions[parent]);
+                distance_a.partial_cmp(&distance_b).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        let mut order = Vec::with_capacity(parents.len());
+        let mut stack: Vec<usize> = roots.iter().rev().cloned().collect();
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            stack.extend(children[index].iter().rev());
+        }
+        Hierarchy { roots, children, order }
+    }
+
+    fn coordinates(&self) -> Coordinates {
+        if self.roots.len() == 1 && self.children.iter().enumerate().all(|(index, c)| index == self.roots[0] || c.is_empty()) {
+            Coordinates::DemocraticHeliocentric
+        } else {
+            Coordinates::Jacobi
+        }
+    }
+
+    //the star of the heliocentric coordinates and every parent need mass, otherwise there is no Keplerian orbit to drift along
+    fn massless_center(&self, masses: &[f64]) -> Option<usize> {
+        let star = match self.coordinates() {
+            Coordinates::DemocraticHeliocentric => Some(self.roots[0]),
+            Coordinates::Jacobi => None
+        };
+        (0..masses.len()).find(|index| masses[*index] <= 0.0 && (!self.children[*index].is_empty() || star == Some(*index)))
+    }
+
+}
+
+fn apply_physics(
+    mut query: Query<(Entity, &Mass, &mut Acceleration, &mut OrbitSettings, &mut Velocity, &mut SimPosition, &mut Transform, Option<&BodyParent>), Without<KeplerOrbit>>,
+    time: Res<Time>,
+    speed: Res<Speed>,
+    sub_steps: Res<SubSteps>,
+    forces: Res<ForceSettings>,
+    test_particles: Query<(), With<TestParticle>>,
+    perturbation_sources: PerturbationSources,
+    names: Query<&Name>,
+    ephemeris_driven: Query<(), With<EphemerisDriven>>,
+    mut span: ResMut<StepSpan>,
+    integration_type: Res<State<IntegrationType>>,
+    mut next_integration_type: ResMut<NextState<IntegrationType>>,
+    mut toasts: ResMut<ToastContainer>,
+    mut warned: Local<bool>,
+    mut diagnostics: Diagnostics,
+) {
+    let start = Instant::now();
+    let delta = time.delta_secs_f64();
+    let timestep = delta * speed.0;
+    if integration_type.is_changed() {
+        *warned = false;
+    }
+    let perturbations = perturbation_sources.collect(query.iter().map(|(entity, ..)| entity));
+    let indices: HashMap<Entity, usize> = query.iter().enumerate().map(|(index, (entity, _, _, _, _, _, _, _))| (entity, index)).collect();
+    let masses: Vec<f64> = query.iter().map(|(entity, mass, _, _, _, _, _, _)| gravitational_mass(entity, mass, &test_particles)).collect();
+    let parents: Vec<Option<usize>> = query.iter().map(|(_, _, _, _, _, _, _, parent)| parent.and_then(|p| indices.get(&p.0).cloned())).collect();
+    let mut positions: Vec<DVec3> = query.iter().map(|(_, _, _, _, _, pos, _, _)| pos.current).collect();
+    let mut velocities: Vec<DVec3> = query.iter().map(|(_, _, _, _, vel, _, _, _)| vel.0).collect();
+    if positions.is_empty() {
+        return;
+    }
+    let hierarchy = Hierarchy::new(&parents, &positions);
+    //a body whose parent isn't integrated would silently become a root
+    let orphan = query.iter().find(|(_, _, _, _, _, _, _, parent)| parent.is_some_and(|p| !indices.contains_key(&p.0))).map(|(entity, ..)| entity);
+    //a body following an ephemeris can't be moved along a Keplerian drift
+    let driven = query.iter().find(|(entity, ..)| ephemeris_driven.contains(*entity)).map(|(entity, ..)| entity);
+    let name = |entity: Entity| names.get(entity).map(|n| n.to_string()).unwrap_or_default();
+    let problem = match (orphan, driven, hierarchy.massless_center(&masses)) {
+        (Some(entity), _, _) => Some(format!("{} orbits a body that isn't integrated", name(entity))),
+        (None, Some(entity), _) => Some(format!("{} follows an ephemeris", name(entity))),
+        (None, None, Some(index)) => query.iter().nth(index).map(|(entity, ..)| format!("{} has no mass to orbit around", name(entity))),
+        (None, None, None) => None,
+    };
+    if let Some(problem) = problem {
+        //the bodies don't move this step, so neither does the sim time
+        span.0 = 0.0;
+        next_integration_type.set(IntegrationType::Yoshida4);
+        if !*warned {
+            let message = format!("{}, Wisdom-Holman can't handle this scenario. Switched to {}", problem, IntegrationType::Yoshida4.as_str());
+            toasts.0.add(warning_toast(message.as_str()));
+            *warned = true;
+        }
+        return;
+    }
+    let mut start_step = Instant::now();
+    match hierarchy.coordinates() {
+        Coordinates::DemocraticHeliocentric => {
+            let mut state = HeliocentricState::new(&hierarchy, &masses, &positions, &velocities);
+            for _ in 0..sub_steps.0 {
+                start_step = Instant::now();
+                state.step(timestep, &forces, &perturbations);
+            }
+            state.to_absolute(&mut positions, &mut velocities);
+        }
+        Coordinates::Jacobi => {
+            let mut state = JacobiState::new(&hierarchy, &masses, &positions, &velocities, &forces, &perturbations);
+            for _ in 0..sub_steps.0 {
+                start_step = Instant::now();
+                state.step(timestep, &forces, &perturbations);
+            }
+            positions = state.transform.from_jacobi(&state.positions);
+            velocities = state.transform.from_jacobi(&state.velocities);
+        }
+    }
+    diagnostics.add_measurement(&NBODY_STEP_TIME, || start_step.elapsed().as_nanos() as f64);
+    let accelerations = calculate_accelerations(&positions, &velocities, &masses, &forces, &perturbations);
+    for (index, (_, _, mut acc, _, mut vel, mut pos, _, _)) in query.iter_mut().enumerate() {
+        acc.0 = accelerations[index];
+        vel.0 = velocities[index];
+        pos.current = positions[index];
+    }
+    diagnostics.add_measurement(&NBODY_TOTAL_TIME, || start.elapsed().as_nanos() as f64);
+    diagnostics.add_measurement(&NBODY_STEPS, || sub_steps.0 as f64 / delta);
+}
+
+//Heliocentric positions and barycentric velocities of the planets, the star is only implicit
+struct HeliocentricState {
+    star: usize,
+    planets: Vec<usize>,
+    star_mass: f64,
+    planet_masses: Vec<f64>,
+    masses: Vec<f64>,
+    total_mass: f64,
+    positions: Vec<DVec3>,
+    velocities: Vec<DVec3>,
+    barycenter: DVec3,
+    barycenter_velocity: DVec3,
+}
+
+impl HeliocentricState {
+
+    fn new(hierarchy: &Hierarchy, masses: &[f64], positions: &[DVec3], velocities: &[DVec3]) -> Self {
+        let star = hierarchy.roots[0];
+        let planets = hierarchy.children[star].clone();
+        let total_mass: f64 = masses.iter().sum();
+        let barycenter = positions.iter().zip(masses).map(|(p, m)| *p * *m).sum::<DVec3>() / total_mass;
+        let barycenter_velocity = velocities.iter().zip(masses).map(|(v, m)| *v * *m).sum::<DVec3>() / total_mass;
+        HeliocentricState {
+            star,
+            star_mass: masses[star],
+            planet_masses: planets.iter().map(|p| masses[*p]).collect(),
+            masses: masses.to_vec(),
+            total_mass,
+            positions: planets.iter().map(|p| positions[*p] - positions[star]).collect(),
+            velocities: planets.iter().map(|p| velocities[*p] - barycenter_velocity).collect(),
+            planets,
+            barycenter,
+            barycenter_velocity,
+        }
+    }
+
+    fn step(&mut self, timestep: f64, forces: &ForceSettings, perturbations: &Perturbations) {
+        self.kick(timestep / 2.0, forces, perturbations);
+        self.star_drift(timestep / 2.0);
+        let mu = G * self.star_mass;
+        for index in 0..self.positions.len() {
+            let (position, velocity) = kepler_drift(self.positions[index], self.velocities[index], mu, timestep);
+            self.positions[index] = position;
+            self.velocities[index] = velocity;
+        }
+        self.star_drift(timestep / 2.0);
+        self.kick(timestep / 2.0, forces, perturbations);
+        self.barycenter += self.barycenter_velocity * timestep;
+    }
+
+    //interactions between the planets, the star is already part of the Keplerian drift
+    fn kick(&mut self, timestep: f64, forces: &ForceSettings, perturbations: &Perturbations) {
+        let mut accelerations = calculate_accelerations(&self.positions, &self.velocities, &self.planet_masses, forces, &Perturbations::default());
+        if !perturbations.is_empty() {
+            //perturbations only depend on the relative positions and velocities, so the star can sit at the origin.
+            //They don't change the total momentum, so the star's share follows from the momentum of the planets
+            let mut positions = vec![DVec3::ZERO; self.masses.len()];
+            let mut velocities = vec![self.star_velocity(); self.masses.len()];
+            for (index, planet) in self.planets.iter().enumerate() {
+                positions[*planet] = self.positions[index];
+                velocities[*planet] = self.velocities[index];
+            }
+            let mut extra = vec![DVec3::ZERO; self.masses.len()];
+            perturbations.apply(&positions, &velocities, &self.masses, &mut extra);
+            for (index, planet) in self.planets.iter().enumerate() {
+                accelerations[index] += extra[*planet];
+            }
+        }
+        for (velocity, acceleration) in self.velocities.iter_mut().zip(accelerations) {
+            *velocity += acceleration * timestep;
+        }
+    }
+
+    //the motion of the star caused by the momentum of the planets
+    fn star_drift(&mut self, timestep: f64) {
+        let momentum = self.velocities.iter().zip(&self.planet_masses).map(|(v, m)| *v * *m).sum::<DVec3>();
+        let shift = momentum / self.star_mass * timestep;
+        for position in self.positions.iter_mut() {
+            *position += shift;
+        }
+    }
+
+    //barycentric velocity of the star, it balances the momentum of the planets
+    fn star_velocity(&self) -> DVec3 {
+        -self.velocities.iter().zip(&self.planet_masses).map(|(v, m)| *v * *m).sum::<DVec3>() / self.star_mass
+    }
+
+    fn to_absolute(&self, positions: &mut [DVec3], velocities: &mut [DVec3]) {
+        let weighted_positions = self.positions.iter().zip(&self.planet_masses).map(|(p, m)| *p * *m).sum::<DVec3>();
+        let star_position = self.barycenter - weighted_positions / self.total_mass;
+        positions[self.star] = star_position;
+        velocities[self.star] = self.barycenter_velocity + self.star_velocity();
+        for (index, planet) in self.planets.iter().enumerate() {
+            positions[*planet] = star_position + self.positions[index];
+            velocities[*planet] = self.barycenter_velocity + self.velocities[index];
+        }
+    }
+
+}
+
+//Linear transformation between absolute and hierarchical Jacobi coordinates.
+//Every body stores its position relative to the barycenter of its parent and the siblings inside of it,
+//the first root stores the barycenter of the whole system.
+struct JacobiTransform<'a> {
+    hierarchy: &'a Hierarchy,
+    masses: &'a [f64],
+    //mass of every body including all of its descendants
+    system_masses: Vec<f64>,
+    //mass of everything the Jacobi coordinate of a body is relative to, including the body itself
+    interior_masses: Vec<f64>,
+}
+
+impl<'a> JacobiTransform<'a> {
+
+    fn new(hierarchy: &'a Hierarchy, masses: &'a [f64]) -> Self {
+        let mut system_masses = masses.to_vec();
+        for index in hierarchy.order.iter().rev() {
+            for child in &hierarchy.children[*index] {
+                system_masses[*index] += system_masses[*child];
+            }
+        }
+        let mut interior_masses = vec![0.0; masses.len()];
+        let mut chain = |first_mass: f64, members: &[usize]| {
+            let mut mass = first_mass;
+            for member in members {
+                mass += system_masses[*member];
+                interior_masses[*member] = mass;
+            }
+        };
+        for index in &hierarchy.order {
+            chain(masses[*index], &hierarchy.children[*index]);
+        }
+        chain(system_masses[hierarchy.roots[0]], &hierarchy.roots[1..]);
+        JacobiTransform { hierarchy, masses, system_masses, interior_masses }
+    }
+
+    fn to_jacobi(&self, values: &[DVec3]) -> Vec<DVec3> {
+        //barycentric value of every body together with its descendants
+        let mut system_values: Vec<DVec3> = values.to_vec();
+        for index in self.hierarchy.order.iter().rev() {
+            if self.system_masses[*index] > 0.0 {
+                let weighted = self.hierarchy.children[*index].iter().map(|c| system_values[*c] * self.system_masses[*c]).sum::<DVec3>();
+                system_values[*index] = (values[*index] * self.masses[*index] + weighted) / self.system_masses[*index];
+            }
+        }
+        let mut jacobi = vec![DVec3::ZERO; values.len()];
+        let roots = &self.hierarchy.roots;
+        jacobi[roots[0]] = self.chain_to_jacobi(system_values[roots[0]], self.system_masses[roots[0]], &roots[1..], &system_values, &mut jacobi);
+        for index in &self.hierarchy.order {
+            self.chain_to_jacobi(values[*index], self.masses[*index], &self.hierarchy.children[*index], &system_values, &mut jacobi);
+        }
+        jacobi
+    }
+
+    //returns the barycentric value of the whole chain
+    fn chain_to_jacobi(&self, first_value: DVec3, first_mass: f64, members: &[usize], system_values: &[DVec3], jacobi: &mut [DVec3]) -> DVec3 {
+        let mut inner = first_value;
+        let mut inner_mass = first_mass;
+        for member in members {
+            jacobi[*member] = system_values[*member] - inner;
+            let mass = inner_mass + self.system_masses[*member];
+            if mass > 0.0 {
+                inner = (inner * inner_mass + system_values[*member] * self.system_masses[*member]) / mass;
+            }
+            inner_mass = mass;
+        }
+        inner
+    }
+
+    fn from_jacobi(&self, jacobi: &[DVec3]) -> Vec<DVec3> {
+        let mut system_values = vec![DVec3::ZERO; jacobi.len()];
+        let mut values = vec![DVec3::ZERO; jacobi.len()];
+        let roots = &self.hierarchy.roots;
+        system_values[roots[0]] = self.chain_from_jacobi(jacobi[roots[0]], &roots[1..], jacobi, &mut system_values);
+        for index in &self.hierarchy.order {
+            let barycenter = system_values[*index];
+            values[*index] = self.chain_from_jacobi(barycenter, &self.hierarchy.children[*index], jacobi, &mut system_values);
+        }
+        values
+    }
+
+    //walks the chain from the outside in and returns the value of the first member
+    fn chain_from_jacobi(&self, barycenter: DVec3, members: &[usize], jacobi: &[DVec3], system_values: &mut [DVec3]) -> DVec3 {
+        let mut outer = barycenter;
+        for member in members.iter().rev() {
+            let share = if self.interior_masses[*member] > 0.0 {
+                self.system_masses[*member] / self.interior_masses[*member]
+            } else {
+                0.0
+            };
+            let inner = outer - jacobi[*member] * share;
+            system_values[*member] = inner + jacobi[*member];
+            outer = inner;
+        }
+        outer
+    }
+
+}
+
+struct JacobiState<'a> {
+    transform: JacobiTransform<'a>,
+    positions: Vec<DVec3>,
+    velocities: Vec<DVec3>,
+    accelerations: Vec<DVec3>,
+}
+
+impl<'a> JacobiState<'a> {
+
+    fn new(hierarchy: &'a Hierarchy, masses: &'a [f64], positions: &[DVec3], velocities: &[DVec3], forces: &ForceSettings, perturbations: &Perturbations) -> Self {
+        let transform = JacobiTransform::new(hierarchy, masses);
+        let jacobi_positions = transform.to_jacobi(positions);
+        let jacobi_velocities = transform.to_jacobi(velocities);
+        let accelerations = transform.to_jacobi(&calculate_accelerations(positions, velocities, masses, forces, perturbations));
+        JacobiState { transform, positions: jacobi_positions, velocities: jacobi_velocities, accelerations }
+    }
+
+    fn step(&mut self, timestep: f64, forces: &ForceSettings, perturbations: &Perturbations) {
+        self.kick(timestep / 2.0);
+        let system = self.transform.hierarchy.roots[0];
+        for index in 0..self.positions.len() {
+            if index == system {
+                self.positions[index] += self.velocities[index] * timestep;
+                continue;
+            }
+            let (position, velocity) = kepler_drift(self.positions[index], self.velocities[index], self.mu(index), timestep);
+            self.positions[index] = position;
+            self.velocities[index] = velocity;
+        }
+        let absolute_positions = self.transform.from_jacobi(&self.positions);
+        let absolute_velocities = self.transform.from_jacobi(&self.velocities);
+        self.accelerations = self.transform.to_jacobi(&calculate_accelerations(&absolute_positions, &absolute_velocities, self.transform.masses, forces, perturbations));
+        self.kick(timestep / 2.0);
+    }
+
+    //everything except the Keplerian acceleration that is already part of the drift
+    fn kick(&mut self, timestep: f64) {
+        let system = self.transform.hierarchy.roots[0];
+        for index in 0..self.velocities.len() {
+            let mut acceleration = self.accelerations[index];
+            if index != system {
+                let r = self.positions[index].length();
+                if r > 0.0 {
+                    acceleration += self.mu(index) * self.positions[index] / r.powi(3);
+                }
+            }
+            self.velocities[index] += acceleration * timestep;
+        }
+    }
+
+    fn mu(&self, index: usize) -> f64 {
+        G * self.transform.interior_masses[index]
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use crate::simulation::integration::acceleration::calculate_accelerations;
+    use crate::simulation::integration::perturbation::Perturbations;
+    use crate::simulation::integration::ForceSettings;
+
+    use super::{Coordinates, HeliocentricState, Hierarchy, JacobiState, JacobiTransform};
+
+    const DAY: f64 = 86400.0;
+
+    fn offset(values: &[DVec3], derivatives: &[DVec3], timestep: f64) -> Vec<DVec3> {
+        values.iter().zip(derivatives).map(|(value, derivative)| *value + *derivative * timestep).collect()
+    }
+
+    //RK4 on the direct sum with small steps as the reference
+    fn direct(masses: &[f64], positions: &[DVec3], velocities: &[DVec3], timestep: f64, steps: usize) -> Vec<DVec3> {
+        let forces = ForceSettings::default();
+        let perturbations = Perturbations::default();
+        let accelerations = |positions: &[DVec3], velocities: &[DVec3]| calculate_accelerations(positions, velocities, masses, &forces, &perturbations);
+        let mut positions = positions.to_vec();
+        let mut velocities = velocities.to_vec();
+        for _ in 0..steps {
+            let k1_vel = velocities.clone();
+            let k1_acc = accelerations(&positions, &velocities);
+            let k2_vel = offset(&velocities, &k1_acc, timestep / 2.0);
+            let k2_acc = accelerations(&offset(&positions, &k1_vel, timestep / 2.0), &k2_vel);
+            let k3_vel = offset(&velocities, &k2_acc, timestep / 2.0);
+            let k3_acc = accelerations(&offset(&positions, &k2_vel, timestep / 2.0), &k3_vel);
+            let k4_vel = offset(&velocities, &k3_acc, timestep);
+            let k4_acc = accelerations(&offset(&positions, &k3_vel, timestep), &k4_vel);
+            for index in 0..positions.len() {
+                positions[index] += timestep / 6.0 * (k1_vel[index] + 2.0 * k2_vel[index] + 2.0 * k3_vel[index] + k4_vel[index]);
+                velocities[index] += timestep / 6.0 * (k1_acc[index] + 2.0 * k2_acc[index] + 2.0 * k3_acc[index] + k4_acc[index]);
+            }
+        }
+        positions
+    }
+
+    //The same steps as apply_physics
+    fn wisdom_holman(parents: &[Option<usize>], masses: &[f64], positions: &[DVec3], velocities: &[DVec3], timestep: f64, steps: usize) -> Vec<DVec3> {
+        let forces = ForceSettings::default();
+        let perturbations = Perturbations::default();
+        let hierarchy = Hierarchy::new(parents, positions);
+        match hierarchy.coordinates() {
+            Coordinates::DemocraticHeliocentric => {
+                let mut state = HeliocentricState::new(&hierarchy, masses, positions, velocities);
+                for _ in 0..steps {
+                    state.step(timestep, &forces, &perturbations);
+                }
+                let mut positions = positions.to_vec();
+                let mut velocities = velocities.to_vec();
+                state.to_absolute(&mut positions, &mut velocities);
+                positions
+            }
+            Coordinates::Jacobi => {
+                let mut state = JacobiState::new(&hierarchy, masses, positions, velocities, &forces, &perturbations);
+                for _ in 0..steps {
+                    state.step(timestep, &forces, &perturbations);
+                }
+                state.transform.from_jacobi(&state.positions)
+            }
+        }
+    }
+
+    //Sun, Jupiter and Saturn over three orbits of Saturn in 100 day steps, about 43 per orbit of Jupiter
+    #[test]
+    fn outer_planets_follow_the_direct_integration() {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let parents = [None, Some(0), Some(0)];
+        let masses = [1.989e30, 1.898e27, 5.683e26];
+        let positions = [DVec3::ZERO, DVec3::new(7.785e11, 0.0, 0.0), DVec3::new(-1.2e12, -7.8e11, 6e10)];
+        let mut velocities = [DVec3::ZERO, DVec3::new(0.0, 13_700.0, 300.0), DVec3::new(5_100.0, -8_200.0, 0.0)];
+        velocities[0] = -(velocities[1] * masses[1] + velocities[2] * masses[2]) / masses[0];
+        let result = wisdom_holman(&parents, &masses, &positions, &velocities, 100.0 * DAY, 330);
+        let expected = direct(&masses, &positions, &velocities, DAY / 2.0, 330 * 200);
+        for planet in 1..3 {
+            let error = (result[planet] - result[0]).distance(expected[planet] - expected[0]) / (expected[planet] - expected[0]).length();
+            assert!(error < 1e-4, "relative position error {} of planet {}", error, planet);
+        }
+    }
+
+    //Sun, Earth and Moon over a year in half day steps, the Moon makes the hierarchy Jacobi
+    #[test]
+    fn moon_follows_the_direct_integration() {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let parents = [None, Some(0), Some(1)];
+        let masses = [1.989e30, 5.972e24, 7.342e22];
+        let positions = [DVec3::ZERO, DVec3::new(1.496e11, 0.0, 0.0), DVec3::new(1.496e11 + 3.844e8, 0.0, 0.0)];
+        let velocities = [DVec3::ZERO, DVec3::new(0.0, 29_780.0, 0.0), DVec3::new(0.0, 29_780.0 + 1_022.0, 90.0)];
+        let hierarchy = Hierarchy::new(&parents, &positions);
+        assert!(matches!(hierarchy.coordinates(), Coordinates::Jacobi));
+        let result = wisdom_holman(&parents, &masses, &positions, &velocities, DAY / 2.0, 730);
+        let expected = direct(&masses, &positions, &velocities, 600.0, 365 * 144);
+        let earth_error = result[1].distance(expected[1]) / expected[1].length();
+        let moon_error = (result[2] - result[1]).distance(expected[2] - expected[1]) / (expected[2] - expected[1]).length();
+        assert!(earth_error < 1e-6, "relative position error {} of the Earth", earth_error);
+        assert!(moon_error < 1e-2, "relative position error {} of the Moon", moon_error);
+    }
+
+    //Two stars, planets with a moon and a massless particle, so every chain of the transform is used
+    #[test]
+    fn jacobi_round_trip() {
+        let parents = [None, Some(0), Some(0), Some(1), None, Some(4), Some(1)];
+        let masses = [2e30, 6e24, 1.9e27, 7.3e22, 1e30, 3e26, 0.0];
+        let positions = [
+            DVec3::new(1e9, -2e9, 3e8),
+            DVec3::new(1.5e11, 0.0, 1e9),
+            DVec3::new(-7.8e11, 1e10, 0.0),
+            DVec3::new(1.504e11, 2e8, 1e9),
+            DVec3::new(4e12, 3e12, -1e11),
+            DVec3::new(4.1e12, 3e12, -1e11),
+            DVec3::new(1.5e11, 5e7, 1e9),
+        ];
+        let velocities = [
+            DVec3::new(10.0, -5.0, 0.1),
+            DVec3::new(0.0, 29_800.0, 10.0),
+            DVec3::new(-100.0, -13_000.0, 0.0),
+            DVec3::new(-1_000.0, 29_800.0, 50.0),
+            DVec3::new(-3_000.0, 4_000.0, 0.0),
+            DVec3::new(-3_000.0, 20_000.0, 0.0),
+            DVec3::new(7_000.0, 29_800.0, 10.0),
+        ];
+        let hierarchy = Hierarchy::new(&parents, &positions);
+        assert!(matches!(hierarchy.coordinates(), Coordinates::Jacobi));
+        let transform = JacobiTransform::new(&hierarchy, &masses);
+        for (values, tolerance) in [(&positions, 1e-3), (&velocities, 1e-9)] {
+            let round_trip = transform.from_jacobi(&transform.to_jacobi(values));
+            for (value, expected) in round_trip.iter().zip(values.iter()) {
+                assert!(value.distance(*expected) < tolerance, "{} instead of {}", value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn massless_star_is_rejected() {
+        let positions = [DVec3::ZERO, DVec3::X * 1e11];
+        let hierarchy = Hierarchy::new(&[None, Some(0)], &positions);
+        assert_eq!(hierarchy.massless_center(&[0.0, 6e24]), Some(0));
+        assert_eq!(hierarchy.massless_center(&[2e30, 0.0]), None);
+    }
+}
@@ -0,0 +1,49 @@
+use bevy::math::DVec3;
+
+use crate::simulation::asset::serialization::{SerializedBody, SerializedVec};
+
+//Mass weighted mean position and velocity of the bodies (mass, position, velocity).
+//Test particles should be left out, they don't attract anything and so don't move the barycenter
+pub fn barycenter(bodies: impl Iterator<Item = (f64, DVec3, DVec3)>) -> Option<(DVec3, DVec3)> {
+    let mut total_mass = 0.0;
+    let mut position = DVec3::ZERO;
+    let mut velocity = DVec3::ZERO;
+    for (mass, body_position, body_velocity) in bodies {
+        total_mass += mass;
+        position += body_position * mass;
+        velocity += body_velocity * mass;
+    }
+    if total_mass <= 0.0 {
+        return None;
+    }
+    Some((position / total_mass, velocity / total_mass))
+}
+
+//Shifts the starting data of every body so the barycenter rests at the origin and the total momentum is zero.
+//Returns the position and velocity that were subtracted, in the units of the starting data
+pub fn to_barycentric(bodies: &mut [SerializedBody]) -> Option<(DVec3, DVec3)> {
+    let mut states = Vec::new();
+    collect_states(bodies, &mut states);
+    let offset = barycenter(states.into_iter());
+    if let Some((position, velocity)) = offset {
+        shift(bodies, position, velocity);
+    }
+    offset
+}
+
+fn collect_states(bodies: &[SerializedBody], states: &mut Vec<(f64, DVec3, DVec3)>) {
+    for body in bodies {
+        if !body.data.test_particle {
+            states.push((body.data.mass, DVec3::from(body.data.starting_position), DVec3::from(body.data.starting_velocity)));
+        }
+        collect_states(&body.children, states);
+    }
+}
+
+fn shift(bodies: &mut [SerializedBody], position: DVec3, velocity: DVec3) {
+    for body in bodies {
+        body.data.starting_position = SerializedVec::from(DVec3::from(body.data.starting_position) - position);
+        body.data.starting_velocity = SerializedVec::from(DVec3::from(body.data.starting_velocity) - velocity);
+        shift(&mut body.children, position, velocity);
+    }
+}
@@ -120,7 +120,7 @@ fn update_progress(
     let text0 = loading_text("Spawning bodies", loading_state.spawned_bodies, false);
     let text1 = loading_text(format!("Scaling bodies: {}/{}", loading_state.scaled_bodies_count, loading_state.total_bodies).as_str(), loading_state.scaled_bodies, false);
     let text2 = loading_text("Rotating bodies", loading_state.tilted_bodies, false);
-    let text3 = loading_text(format!("Loading SPK files: {}/{}", loading_state.spice_loaded, loading_state.spice_total).as_str(), loading_state.loaded_spice_files, *sim_type == SimStateType::Simulation);
+    let text3 = loading_text(format!("Loading SPK files: {}/{}", loading_state.spice_loaded, loading_state.spice_total).as_str(), loading_state.loaded_spice_files, *sim_type == SimStateType::Simulation && loading_state.spice_total == 0);
     let new_text = format!("{}\n{}\n{}\n{}", text0, text1, text2, text3);
     if let Ok(mut text) = marker.single_mut() {
         let old_text = text.0.clone();
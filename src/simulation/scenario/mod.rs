@@ -1,3 +1,4 @@
 pub mod save_scenario;
 pub mod loading;
 pub mod setup;
+pub mod barycenter;
@@ -1,16 +1,16 @@
-use crate::simulation::asset::serialization::{SerializedBody, SerializedBodyData, SerializedFixedBodyFrame, SerializedLightSource, SerializedMat3, SerializedVec, SimulationData};
-use crate::simulation::components::body::{BodyChildren, BodyRotation, BodyShape, LightSource, Mass, ModelPath, RotationSpeed, SimPosition, Star, Velocity};
+use crate::simulation::asset::serialization::{SerializedAtmosphere, SerializedBody, SerializedBodyData, SerializedFixedBodyFrame, SerializedLightSource, SerializedMat3, SerializedRadiationPressure, SerializedVec, SerializedZonalHarmonics, SimulationData};
+use crate::simulation::components::body::{Atmosphere, BodyChildren, BodyRotation, BodyShape, EphemerisDriven, KeplerOrbit, LightSource, Mass, ModelPath, RadiationPressure, RotationSpeed, SimPosition, Star, TestParticle, Velocity, ZonalHarmonics};
+use crate::simulation::components::collision::CollisionSettings;
 use crate::simulation::components::horizons::AniseMetadata;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::ForceSettings;
 use crate::simulation::scenario::setup::ScenarioData;
 use crate::simulation::ui::scenario_selection::SelectedScenario;
 use crate::simulation::ui::toast::{success_toast, ToastContainer};
-use anise::structure::planetocentric::ellipsoid::Ellipsoid;
 use bevy::ecs::system::SystemParam;
-use bevy::math::DVec3;
 use bevy::prelude::Name;
-use bevy::prelude::{Assets, Entity, Query, Res, ResMut};
+use bevy::prelude::{Assets, Entity, Query, Res, ResMut, With};
 use std::fs;
 
 #[derive(SystemParam)]
@@ -20,9 +20,17 @@ pub struct SystemPanelSet<'w, 's> {
     scenario_data: ResMut<'w, ScenarioData>,
     bodies: Query<'w, 's, (Entity, &'static Mass, &'static SimPosition, &'static Velocity, &'static Name, &'static ModelPath, &'static BodyShape, &'static RotationSpeed, &'static BodyRotation, Option<&'static BodyChildren>, &'static AniseMetadata, &'static BodyRotation, Option<&'static Star>)>,
     lights: Query<'w, 's, &'static LightSource>,
+    test_particles: Query<'w, 's, (), With<TestParticle>>,
+    kepler_bodies: Query<'w, 's, (), With<KeplerOrbit>>,
+    ephemeris_bodies: Query<'w, 's, (), With<EphemerisDriven>>,
+    harmonics: Query<'w, 's, &'static ZonalHarmonics>,
+    radiation: Query<'w, 's, &'static RadiationPressure>,
+    atmospheres: Query<'w, 's, &'static Atmosphere>,
     toasts: ResMut<'w, ToastContainer>,
     scale: Res<'w, SimulationScale>,
-    speed: Res<'w, Speed>
+    speed: Res<'w, Speed>,
+    forces: Res<'w, ForceSettings>,
+    collisions: Res<'w, CollisionSettings>
 
 }
 
@@ -40,6 +48,11 @@ pub fn save_scenario(
         scale: system_panel_set.scale.0,
         timestep: system_panel_set.speed.0 as i32,
         data_sets: scenario_data.spice_files.keys().cloned().collect(),
+        softening: system_panel_set.forces.softening / 1000.0,
+        collisions: system_panel_set.collisions.mode,
+        roche_breakup: system_panel_set.collisions.roche_breakup,
+        relativity: system_panel_set.forces.relativity,
+        barycentric_correction: scenario_data.barycentric_correction,
     };
     let serialized_data = serde_json::to_string_pretty(&simulation_data).unwrap();
     fs::write(format!("scenarios/{}", file_path), serialized_data).unwrap();
@@ -86,40 +99,31 @@ fn collect_moons(system_panel_set: &SystemPanelSet, children: BodyChildren) -> V
 fn find_body_data(system_panel_set: &SystemPanelSet, entity: Entity) -> Option<(SerializedBodyData, Option<BodyChildren>)> {
     system_panel_set.bodies.iter().find(|(e, _, _, _, _, _, _, _, _, _, _, _, _)| *e == entity)
         .map(|(_, m, p, v, n, mp, d, rs, _at, child, naif, rotation, _)| (
-            create_serialized_body_data(m.0, p.current / 1000.0, v.0 / 1000.0, n.to_string(), mp.cleaned(), rs.0, None, naif.clone(), d.ellipsoid, *rotation),
-            child.map(|c| c.clone())
+            SerializedBodyData {
+                mass: m.0,
+                starting_position: SerializedVec::from(p.current / 1000.0),
+                starting_velocity: SerializedVec::from(v.0 / 1000.0),
+                name: n.to_string(),
+                model_path: mp.cleaned(),
+                rotation_speed: rs.0,
+                simulate: !system_panel_set.kepler_bodies.contains(entity),
+                ellipsoid: d.ellipsoid,
+                light_source: None,
+                naif_id: naif.ephemeris_id,
+                fixed_body_frame: SerializedFixedBodyFrame {
+                    target_id: naif.target_id,
+                    orientation_id: naif.orientation_id
+                },
+                rotation_matrix: SerializedMat3::from(rotation.matrix),
+                test_particle: system_panel_set.test_particles.contains(entity),
+                ephemeris_driven: system_panel_set.ephemeris_bodies.contains(entity),
+                zonal_harmonics: system_panel_set.harmonics.get(entity).ok().map(|h| SerializedZonalHarmonics::from(*h)),
+                radiation_pressure: system_panel_set.radiation.get(entity).ok().map(|r| SerializedRadiationPressure::from(*r)),
+                atmosphere: system_panel_set.atmospheres.get(entity).ok().map(|a| SerializedAtmosphere::from(*a))
+            },
+            child.cloned()
         ))
 }
-fn create_serialized_body_data(
-    mass: f64,
-    position: DVec3,
-    velocity: DVec3,
-    name: String,
-    model_path: String,
-    rotation_speed: f64,
-    light_source: Option<SerializedLightSource>,
-    anise_metadata: AniseMetadata,
-    ellipsoid: Ellipsoid,
-    rotation: BodyRotation
-) -> SerializedBodyData {
-    SerializedBodyData {
-        mass,
-        starting_position: SerializedVec::from(position),
-        starting_velocity: SerializedVec::from(velocity),
-        name,
-        model_path,
-        rotation_speed,
-        simulate: true,
-        ellipsoid,
-        light_source,
-        naif_id: anise_metadata.ephemeris_id,
-        fixed_body_frame: SerializedFixedBodyFrame {
-            target_id: anise_metadata.target_id,
-            orientation_id: anise_metadata.orientation_id
-        },
-        rotation_matrix: SerializedMat3::from(rotation.matrix)
-    }
-}
 
 fn find_light_source(
     system_panel_set: &SystemPanelSet,
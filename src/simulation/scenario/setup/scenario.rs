@@ -1,14 +1,17 @@
 use crate::simulation::asset::serialization::{SerializedBody, SerializedLightSource, SimulationData};
 use crate::simulation::components::apsis::ApsisBody;
-use crate::simulation::components::body::{BodyBundle, BodyChildren, BodyParent, LightSource, Moon, OrbitSettings, Planet, SceneEntity, SceneHandle, Star};
+use crate::simulation::components::collision::CollisionSettings;
+use crate::simulation::components::body::{Atmosphere, BodyBundle, BodyChildren, BodyParent, EphemerisDriven, KeplerOrbit, LightSource, Moon, OrbitSettings, Planet, SceneEntity, SceneHandle, RadiationPressure, Star, TestParticle, ZonalHarmonics};
 use crate::simulation::components::editor::CreateBodyType;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::selection::{SelectedEntity, SELECTION_MULTIPLIER};
+use crate::simulation::integration::ForceSettings;
 use crate::simulation::render::star_billboard::{StarBillboard, SunImposterMaterial};
+use crate::simulation::scenario::barycenter::to_barycentric;
 use crate::simulation::scenario::loading::LoadingState;
 use crate::simulation::ui::scenario_selection::SelectedScenario;
 use crate::simulation::units::converter::scale_lumen;
-use crate::simulation::SimState;
+use crate::simulation::{SimState, SimStateType};
 use bevy::asset::AssetServer;
 use bevy::color::palettes::css::WHITE;
 use bevy::ecs::system::EntityCommands;
@@ -31,7 +34,10 @@ pub struct ScenarioData {
     pub description: String,
     pub timestep: i32,
    // pub scale: f32,
-    pub spice_files: HashMap<String, bool>
+    pub spice_files: HashMap<String, bool>,
+    pub barycentric_correction: bool,
+    //position (m) and velocity (m/s) of the barycenter at the start, subtracted from the bodies when the simulation was loaded
+    pub barycentric_offset: (DVec3, DVec3)
 
 }
 
@@ -44,11 +50,23 @@ impl From<SimulationData> for ScenarioData {
             description: value.description,
             timestep: value.timestep,
           //  scale: value.scale,
-            spice_files: value.data_sets.iter().map(|d| (d.clone(), false)).collect()
+            spice_files: value.data_sets.iter().map(|d| (d.clone(), false)).collect(),
+            barycentric_correction: value.barycentric_correction,
+            barycentric_offset: (DVec3::ZERO, DVec3::ZERO)
         }
     }
 }
 
+impl ScenarioData {
+
+    //Moves a state relative to the solar system barycenter (from the kernels) into the frame the bodies are simulated in
+    pub fn to_simulation_frame(&self, seconds: f64, position: DVec3, velocity: DVec3) -> (DVec3, DVec3) {
+        let (offset_position, offset_velocity) = self.barycentric_offset;
+        (position - offset_position - offset_velocity * seconds, velocity - offset_velocity)
+    }
+
+}
+
 pub fn setup_scenario(
     mut commands: Commands,
     assets: Res<AssetServer>,
@@ -61,7 +79,10 @@ pub fn setup_scenario(
     mut sun_materials: ResMut<Assets<SunImposterMaterial>>,
     mut sim_state: ResMut<NextState<SimState>>,
     scale: Res<SimulationScale>,
-    mut cam: Query<&mut PanOrbitCamera>
+    mut cam: Query<&mut PanOrbitCamera>,
+    mut forces: ResMut<ForceSettings>,
+    mut collisions: ResMut<CollisionSettings>,
+    sim_type: Res<SimStateType>
 ) {
     if selected_scenario.spawned {
         return;
@@ -73,11 +94,23 @@ pub fn setup_scenario(
     }
     let data = bodies.unwrap();
     *scenario_data = ScenarioData::from(data.clone());
+    forces.softening = data.softening * 1000.0;
+    forces.relativity = data.relativity;
+    collisions.mode = data.collisions;
+    collisions.roche_breakup = data.roche_breakup;
+
+    let mut serialized_bodies = data.bodies.clone();
+    //the editor keeps the stored starting data, so saving doesn't change it
+    if data.barycentric_correction && *sim_type == SimStateType::Simulation {
+        if let Some((position, velocity)) = to_barycentric(&mut serialized_bodies) {
+            scenario_data.barycentric_offset = (position * 1000.0, velocity * 1000.0);
+        }
+    }
 
     let mut stars = vec![];
     //iterate through the stars
     recursive_bodies(
-        data.bodies.iter().collect(),
+        serialized_bodies.iter().collect(),
         &mut commands,
         &scale,
         &assets,
@@ -120,9 +153,6 @@ pub fn recursive_bodies(
     let total_count = bodies.iter().count();
     *count += total_count as i32;
     for (index, serialized_body) in bodies.iter().enumerate() {
-        if !serialized_body.data.simulate {
-            continue;
-        }
         let id = commands.spawn((Visibility::default(), Transform::default())).id();
 
         //planets vector for adding BodyChildren later
@@ -149,6 +179,24 @@ pub fn recursive_bodies(
         if let Some(parent) = parent {
             body.insert(BodyParent(parent));
         }
+        if !serialized_body.data.simulate {
+            body.insert(KeplerOrbit::default());
+        }
+        if serialized_body.data.test_particle {
+            body.insert(TestParticle);
+        }
+        if serialized_body.data.ephemeris_driven {
+            body.insert(EphemerisDriven);
+        }
+        if let Some(harmonics) = serialized_body.data.zonal_harmonics {
+            body.insert(ZonalHarmonics::from(harmonics));
+        }
+        if let Some(radiation) = serialized_body.data.radiation_pressure {
+            body.insert(RadiationPressure::from(radiation));
+        }
+        if let Some(atmosphere) = serialized_body.data.atmosphere {
+            body.insert(Atmosphere::from(atmosphere));
+        }
     }
 }
 
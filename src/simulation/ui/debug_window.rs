@@ -1,14 +1,17 @@
+use std::collections::VecDeque;
 use std::time::Duration;
 
 use crate::simulation::components::body::Mass;
-use crate::simulation::integration::{NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
+use crate::simulation::integration::conservation::{Conservation, ConservedQuantities};
+use crate::simulation::integration::{AdaptiveStepStats, IntegrationType, NBODY_ACCEPTED_STEPS, NBODY_REJECTED_STEPS, NBODY_STEPS, NBODY_STEP_TIME, NBODY_TOTAL_TIME};
 use crate::simulation::ui::system_panel::system_panel;
 use crate::simulation::ui::UiState;
+use crate::simulation::units::text_formatter::{format_length, format_seconds};
 use crate::simulation::SimState;
 use bevy::app::{App, Plugin};
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
-use bevy::prelude::{in_state, IntoScheduleConfigs, Query, Res, ResMut};
-use bevy_egui::egui::RichText;
+use bevy::prelude::{in_state, IntoScheduleConfigs, Query, Res, ResMut, State};
+use bevy_egui::egui::{Align2, Color32, FontId, RichText, Stroke, Ui};
 use bevy_egui::{egui::{self}, EguiContexts, EguiPrimaryContextPass};
 use bevy_panorbit_camera::PanOrbitCamera;
 
@@ -28,7 +31,10 @@ fn debug_window(
     mut ui_state: ResMut<UiState>,
     diagnostics: Res<DiagnosticsStore>,
     bodies: Query<&Mass>,
-    camera: Query<&PanOrbitCamera>
+    camera: Query<&PanOrbitCamera>,
+    integrator: Res<State<IntegrationType>>,
+    adaptive_stats: Res<AdaptiveStepStats>,
+    conservation: Res<Conservation>
 )  {
     if !ui_state.visible || egui_ctx.ctx_mut().is_err() {
         return;
@@ -107,6 +113,30 @@ fn debug_window(
                     });
                 }
             }
+            if **integrator == IntegrationType::DormandPrince {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Accepted steps: ").strong());
+                    ui.label(format!("{}", adaptive_stats.accepted));
+                });
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Rejected steps: ").strong());
+                    ui.label(format!("{}", adaptive_stats.rejected));
+                });
+                if let Some(accepted) = diagnostics.get(&NBODY_ACCEPTED_STEPS).and_then(|d| d.average()) {
+                    let rejected = diagnostics.get(&NBODY_REJECTED_STEPS).and_then(|d| d.average()).unwrap_or(0.0);
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("Accepted / rejected per physics step: ").strong());
+                        ui.label(format!("{:.1} / {:.1}", accepted, rejected));
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Adaptive step size: ").strong());
+                    ui.label(format_seconds(adaptive_stats.step));
+                });
+            }
+            if let (Some(start), Some(current)) = (&conservation.start, &conservation.current) {
+                conservation_section(ui, start, current, &conservation.history);
+            }
             ui.horizontal(|ui| {
                 ui.label(RichText::new("Camera focus: ").strong());                            
                 ui.label(format!("{}", cam.focus));
@@ -117,4 +147,74 @@ fn debug_window(
             });
             ui.allocate_space(egui::vec2(ui.available_size().x, 0.0));
         });
-}
\ No newline at end of file
+}
+
+type Drift = fn(&ConservedQuantities, &ConservedQuantities) -> f64;
+
+const DRIFTS: [(&str, Drift, Color32); 3] = [
+    ("Energy", ConservedQuantities::energy_drift, Color32::LIGHT_BLUE),
+    ("Momentum", ConservedQuantities::momentum_drift, Color32::LIGHT_GREEN),
+    ("Angular momentum", ConservedQuantities::angular_momentum_drift, Color32::ORANGE),
+];
+
+fn conservation_section(ui: &mut Ui, start: &ConservedQuantities, current: &ConservedQuantities, history: &VecDeque<ConservedQuantities>) {
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Total energy: ").strong());
+        ui.label(format!("{:.6e} J (drift {:.2e})", current.energy, current.energy_drift(start)));
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Linear momentum: ").strong());
+        ui.label(format!("{:.6e} kg m/s (drift {:.2e})", current.momentum.length(), current.momentum_drift(start)));
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Angular momentum: ").strong());
+        ui.label(format!("{:.6e} kg m²/s (drift {:.2e})", current.angular_momentum.length(), current.angular_momentum_drift(start)));
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Barycenter: ").strong());
+        ui.label(format!("({:.0}, {:.0}, {:.0}) km", current.barycenter.x / 1000.0, current.barycenter.y / 1000.0, current.barycenter.z / 1000.0));
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Barycenter moved: ").strong());
+        ui.label(format_length(current.barycenter.distance(start.barycenter)));
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("Drift since: ").strong());
+        ui.label(format_seconds(current.time - start.time));
+    });
+    drift_graph(ui, start, history);
+    ui.horizontal(|ui| {
+        for (name, _, color) in DRIFTS {
+            ui.label(RichText::new(name).color(color));
+        }
+    });
+    ui.separator();
+}
+
+//Relative drifts on a log scale, they usually span several orders of magnitude
+fn drift_graph(ui: &mut Ui, start: &ConservedQuantities, history: &VecDeque<ConservedQuantities>) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(250.0, 80.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    if history.len() < 2 {
+        return;
+    }
+    let series: Vec<(Vec<f64>, Color32)> = DRIFTS.iter()
+        .map(|(_, drift, color)| (history.iter().map(|sample| drift(sample, start).max(1e-16).log10()).collect(), *color))
+        .collect();
+    let min = series.iter().flat_map(|(values, _)| values.iter()).fold(f64::INFINITY, |a, b| a.min(*b)).floor();
+    let max = series.iter().flat_map(|(values, _)| values.iter()).fold(f64::NEG_INFINITY, |a, b| a.max(*b)).ceil().max(min + 1.0);
+    for (values, color) in series {
+        let points = values.iter().enumerate().map(|(index, value)| {
+            egui::pos2(
+                rect.left() + rect.width() * index as f32 / (values.len() - 1) as f32,
+                rect.bottom() - rect.height() * ((value - min) / (max - min)) as f32,
+            )
+        }).collect();
+        painter.add(egui::Shape::line(points, Stroke::new(1.5, color)));
+    }
+    let text_color = ui.visuals().text_color();
+    painter.text(rect.left_top(), Align2::LEFT_TOP, format!("1e{:.0}", max), FontId::monospace(10.0), text_color);
+    painter.text(rect.left_bottom(), Align2::LEFT_BOTTOM, format!("1e{:.0}", min), FontId::monospace(10.0), text_color);
+}
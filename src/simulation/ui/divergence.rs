@@ -0,0 +1,239 @@
+use crate::simulation::components::anise::{epoch_at, vector3_to_dvec3, AlmanacHolder};
+use crate::simulation::components::body::{EphemerisDriven, SimPosition, Velocity};
+use crate::simulation::components::horizons::AniseMetadata;
+use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::{advance_sim_time, paused, IntegrationType, SubSteps};
+use crate::simulation::scenario::setup::ScenarioData;
+use crate::simulation::ui::system_panel::system_panel;
+use crate::simulation::ui::toast::{error_toast, success_toast, ToastContainer};
+use crate::simulation::ui::{SimTime, UiState};
+use crate::simulation::units::text_formatter::format_length;
+use crate::simulation::SimState;
+use crate::utils::sim_state_type_simulation;
+use anise::constants::frames::SSB_J2000;
+use anise::constants::orientations::J2000;
+use anise::prelude::Frame;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::prelude::{in_state, not, Entity, IntoScheduleConfigs, Local, Name, OnExit, Query, Res, ResMut, Resource, State, Without};
+use bevy_egui::egui::RichText;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const LOG_DIRECTORY: &str = "divergence";
+const SAMPLE_INTERVAL: u32 = 10; //physics steps between two samples, every sample looks up all bodies in the kernels
+
+pub struct DivergencePlugin;
+
+impl Plugin for DivergencePlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Divergence>()
+            .add_systems(OnExit(SimState::Loaded), clear_divergence)
+            .add_systems(FixedUpdate, (track_divergence).after(advance_sim_time).run_if(sim_state_type_simulation).run_if(not(paused)))
+            .add_systems(EguiPrimaryContextPass, (divergence_window.after(system_panel)).run_if(in_state(SimState::Loaded)));
+    }
+
+}
+
+//Position and velocity error of the simulated bodies against the SPICE ephemeris at the current epoch
+#[derive(Resource, Default)]
+pub struct Divergence {
+
+    pub entries: Vec<DivergenceEntry>,
+    pub sort: DivergenceSort,
+    pub descending: bool,
+    pub logging: bool,
+    log: Option<BufWriter<File>>,
+
+}
+
+#[derive(Clone)]
+pub struct DivergenceEntry {
+
+    pub entity: Entity,
+    pub name: String,
+    pub position: f64, //in m
+    pub velocity: f64, //in m/s
+
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum DivergenceSort {
+    #[default]
+    Name,
+    Position,
+    Velocity
+}
+
+impl Divergence {
+
+    pub fn get(&self, entity: Entity) -> Option<&DivergenceEntry> {
+        self.entries.iter().find(|entry| entry.entity == entity)
+    }
+
+    fn sorted(&self) -> Vec<DivergenceEntry> {
+        let mut entries = self.entries.clone();
+        match self.sort {
+            DivergenceSort::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+            DivergenceSort::Position => entries.sort_by(|a, b| a.position.total_cmp(&b.position)),
+            DivergenceSort::Velocity => entries.sort_by(|a, b| a.velocity.total_cmp(&b.velocity)),
+        }
+        if self.descending {
+            entries.reverse();
+        }
+        entries
+    }
+
+    fn stop_logging(&mut self) {
+        self.logging = false;
+        if let Some(mut log) = self.log.take() {
+            let _ = log.flush();
+        }
+    }
+
+}
+
+fn clear_divergence(
+    mut divergence: ResMut<Divergence>
+) {
+    divergence.stop_logging();
+    divergence.entries.clear();
+}
+
+//Compares every body with an ephemeris id to the loaded kernels every SAMPLE_INTERVAL physics steps.
+//Bodies the kernels don't cover at the current epoch are left out, ephemeris driven bodies have no error to track
+fn track_divergence(
+    bodies: Query<(Entity, &Name, &AniseMetadata, &SimPosition, &Velocity), Without<EphemerisDriven>>,
+    almanac: Res<AlmanacHolder>,
+    scenario: Res<ScenarioData>,
+    sim_time: Res<SimTime>,
+    integrator: Res<State<IntegrationType>>,
+    speed: Res<Speed>,
+    sub_steps: Res<SubSteps>,
+    mut divergence: ResMut<Divergence>,
+    mut toasts: ResMut<ToastContainer>,
+    mut steps: Local<u32>,
+) {
+    let sample = *steps % SAMPLE_INTERVAL == 0;
+    *steps += 1;
+    if !sample {
+        return;
+    }
+    divergence.entries.clear();
+    if !scenario.spice_files.values().any(|loaded| *loaded) {
+        return;
+    }
+    let epoch = epoch_at(&scenario, sim_time.0);
+    for (entity, name, metadata, position, velocity) in &bodies {
+        if metadata.ephemeris_id == -1 {
+            continue;
+        }
+        let Ok(state) = almanac.0.translate(Frame::new(metadata.ephemeris_id, J2000), SSB_J2000, epoch, None) else {
+            continue;
+        };
+        let (expected_position, expected_velocity) = scenario.to_simulation_frame(sim_time.0, vector3_to_dvec3(state.radius_km) * 1000.0, vector3_to_dvec3(state.velocity_km_s) * 1000.0);
+        divergence.entries.push(DivergenceEntry {
+            entity,
+            name: name.to_string(),
+            position: position.current.distance(expected_position),
+            velocity: velocity.0.distance(expected_velocity),
+        });
+    }
+    if !divergence.logging {
+        return;
+    }
+    let integrator = integrator.as_str();
+    if divergence.log.is_none() {
+        match create_log(&scenario.title, &integrator, speed.step()) {
+            Ok((log, path)) => {
+                toasts.0.add(success_toast(format!("Logging the divergence to {}", path).as_str()));
+                divergence.log = Some(log);
+            }
+            Err(e) => {
+                toasts.0.add(error_toast(format!("Couldn't create the divergence log: {:?}", e).as_str()));
+                divergence.logging = false;
+                return;
+            }
+        }
+    }
+    let divergence = divergence.as_mut();
+    let Some(log) = divergence.log.as_mut() else {
+        return;
+    };
+    let written = divergence.entries.iter().try_for_each(|entry| {
+        writeln!(log, "{},{},{},{},{},{},{}", sim_time.0, entry.name.replace(',', " "), integrator, speed.step(), sub_steps.0, entry.position, entry.velocity)
+    });
+    if let Err(e) = written {
+        toasts.0.add(error_toast(format!("Couldn't write the divergence log: {:?}", e).as_str()));
+        divergence.stop_logging();
+    }
+}
+
+//One file per run, named after the scenario, integrator, sub step size and start of the run, so runs can be compared side by side
+fn create_log(title: &str, integrator: &str, timestep: f64) -> std::io::Result<(BufWriter<File>, String)> {
+    fs::create_dir_all(LOG_DIRECTORY)?;
+    let started = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let file_name: String = format!("{}_{}_{}s_{}", title, integrator, timestep, started).chars().map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' }).collect();
+    let path = format!("{}/{}.csv", LOG_DIRECTORY, file_name);
+    let mut log = BufWriter::new(File::create(&path)?);
+    log.write_all(b"time_s,body,integrator,timestep_s,sub_steps,position_error_m,velocity_error_m_s\n")?;
+    Ok((log, path))
+}
+
+fn divergence_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut divergence: ResMut<Divergence>,
+) {
+    if !ui_state.visible || egui_ctx.ctx_mut().is_err() {
+        return;
+    }
+    egui::Window::new("Ephemeris Divergence")
+        .open(&mut ui_state.show_divergence)
+        .collapsible(true)
+        .constrain(true)
+        .scroll([false, true])
+        .default_width(400.0)
+        .show(egui_ctx.ctx_mut().unwrap(), |ui| {
+            if divergence.entries.is_empty() {
+                ui.label("No body is covered by the loaded SPICE kernels");
+            }
+            egui::Grid::new("divergence_table").striped(true).show(ui, |ui| {
+                for (sort, label) in [(DivergenceSort::Name, "Body"), (DivergenceSort::Position, "Position Error"), (DivergenceSort::Velocity, "Velocity Error")] {
+                    let arrow = match (divergence.sort == sort, divergence.descending) {
+                        (false, _) => "",
+                        (true, false) => " ⬆",
+                        (true, true) => " ⬇",
+                    };
+                    if ui.selectable_label(divergence.sort == sort, RichText::new(format!("{}{}", label, arrow)).strong()).clicked() {
+                        if divergence.sort == sort {
+                            divergence.descending = !divergence.descending;
+                        } else {
+                            divergence.sort = sort;
+                            divergence.descending = sort != DivergenceSort::Name;
+                        }
+                    }
+                }
+                ui.end_row();
+                for entry in divergence.sorted() {
+                    ui.label(entry.name);
+                    ui.label(format_length(entry.position));
+                    ui.label(format!("{:.3} m/s", entry.velocity));
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            let mut logging = divergence.logging;
+            ui.checkbox(&mut logging, "Log to CSV").on_hover_text(format!("Writes the errors every {} physics steps to the {} folder", SAMPLE_INTERVAL, LOG_DIRECTORY));
+            if logging != divergence.logging {
+                if logging {
+                    divergence.logging = true;
+                } else {
+                    divergence.stop_logging();
+                }
+            }
+        });
+}
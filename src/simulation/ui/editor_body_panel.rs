@@ -1,6 +1,8 @@
-use crate::simulation::components::body::{BodyRotation, BodyShape, LightSource, Mass, ModelPath, RotationSpeed, SceneEntity, SceneHandle, SimPosition, Velocity};
+use crate::constants::G;
+use crate::simulation::components::body::{Atmosphere, BodyParent, BodyRotation, BodyShape, EphemerisDriven, KeplerOrbit, LightSource, Mass, ModelPath, RadiationPressure, RotationSpeed, SceneEntity, SceneHandle, SimPosition, TestParticle, Velocity, ZonalHarmonics};
 use crate::simulation::components::editor::{EditorSystemType, EditorSystems};
 use crate::simulation::components::horizons::AniseMetadata;
+use crate::simulation::components::orbital_elements::{true_anomaly_from_mean, OrbitalElements};
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::selection::SelectedEntity;
 use crate::simulation::render::star_billboard::{StarBillboard, SunImposterMaterial};
@@ -12,10 +14,12 @@ use anise::structure::planetocentric::ellipsoid::Ellipsoid;
 use bevy::asset::{AssetServer, Assets};
 use bevy::math::DVec3;
 use bevy::pbr::MeshMaterial3d;
-use bevy::prelude::{default, Color, Commands, Entity, Handle, Mat3, Mut, Name, PointLight, Query, Res, ResMut, Resource, Scene, Srgba, Visibility, With};
-use bevy_egui::egui::{Align, Context, Layout, ScrollArea};
+use bevy::prelude::{default, Color, Commands, Entity, Handle, Has, Mat3, Mut, Name, PointLight, Query, Res, ResMut, Resource, Scene, Srgba, Visibility, With};
+use bevy_egui::egui::{Align, Color32, Context, Layout, ScrollArea};
 use bevy_egui::{egui, EguiContexts};
 
+const PARABOLIC_MARGIN: f64 = 1e-4; //smallest distance of the eccentricity from 1 in the element input
+
 #[derive(Debug, Clone, Resource)]
 pub struct EditorPanelState {
     pub entity: Option<Entity>,
@@ -31,7 +35,29 @@ pub struct EditorPanelState {
     pub ephemeris_id: i32,
     pub orientation_id: i32,
     pub target_id: i32,
-    pub rotation_matrix: Mat3
+    pub rotation_matrix: Mat3,
+    pub test_particle: bool,
+    pub kepler_orbit: bool,
+    pub ephemeris_driven: bool,
+    pub zonal_harmonics: ZonalHarmonics,
+    pub radiation_pressure: RadiationPressure,
+    pub atmosphere: Atmosphere,
+    pub element_input: bool,
+    pub elements: ElementInput,
+    pub parent_state: Option<(DVec3, DVec3, f64)> //position, velocity and mass of the parent, refreshed every frame
+}
+
+//Starting state as an orbit around the parent, in km and degrees
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ElementInput {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub anomaly: f64,
+    pub mean_anomaly: bool, //the anomaly is the mean anomaly at the starting time instead of the true anomaly
+    pub invalid: bool,
 }
 
 impl Default for EditorPanelState {
@@ -50,7 +76,16 @@ impl Default for EditorPanelState {
             ephemeris_id: -1,
             orientation_id: -1,
             target_id: -1,
-            rotation_matrix: Mat3::IDENTITY
+            rotation_matrix: Mat3::IDENTITY,
+            test_particle: false,
+            kepler_orbit: false,
+            ephemeris_driven: false,
+            zonal_harmonics: ZonalHarmonics::default(),
+            radiation_pressure: RadiationPressure::default(),
+            atmosphere: Atmosphere::default(),
+            element_input: false,
+            elements: ElementInput::default(),
+            parent_state: None
         }
     }
 }
@@ -67,8 +102,11 @@ pub struct LightSettings {
 pub fn editor_body_panel(
     mut egui_context: EguiContexts,
     selected_entity: Res<SelectedEntity>,
-    mut query: Query<(Entity, &mut Name, &mut SimPosition, &mut Velocity, &mut Mass, &mut BodyShape, &mut RotationSpeed, &mut BodyRotation, &mut ModelPath, &mut SceneHandle, &mut AniseMetadata), With<Mass>>,
+    mut query: Query<(Entity, &mut Name, &mut SimPosition, &mut Velocity, &mut Mass, &mut BodyShape, &mut RotationSpeed, &mut BodyRotation, &mut ModelPath, &mut SceneHandle, &mut AniseMetadata, Has<TestParticle>, Option<&ZonalHarmonics>, Option<&RadiationPressure>, Option<&Atmosphere>), With<Mass>>,
     scene_query: Query<Entity, With<SceneEntity>>,
+    kepler_bodies: Query<(), With<KeplerOrbit>>,
+    ephemeris_bodies: Query<(), With<EphemerisDriven>>,
+    parents: Query<&BodyParent>,
     mut state: ResMut<EditorPanelState>,
     mut commands: Commands,
     systems: Res<EditorSystems>,
@@ -84,11 +122,22 @@ pub fn editor_body_panel(
     }
     let mut apply  = false;
     if let Some(s_entity) = selected_entity.entity {
-        if let Ok((entity, mut name, mut pos, mut vel, mut mass, mut diameter, mut rotation_speed, mut rotation, mut model_path, mut scene, mut horizons_id)) = query.get_mut(s_entity) {
+        let parent_state = parents.get(s_entity).ok().and_then(|parent| query.get(parent.0).ok()).map(|(_, _, pos, vel, mass, ..)| (pos.current, vel.0, mass.0));
+        if let Ok((entity, mut name, mut pos, mut vel, mut mass, mut diameter, mut rotation_speed, mut rotation, mut model_path, mut scene, mut horizons_id, test_particle, harmonics, radiation, atmosphere)) = query.get_mut(s_entity) {
             let light = light_query.iter_mut().find(|(_, l, _)| l.parent == entity).map(|(a,b,c)| (a,b,c));
             let mut billboard_material = billboards.iter_mut().find(|(b, _)| b.0 == entity).map(|(_, m)| m.clone());
             if state.entity.is_none() || state.entity.unwrap() != s_entity {
                 initialize_state(state.as_mut(), s_entity, &name, &pos, &vel, &mass, &diameter, &rotation_speed,&model_path, light.as_ref(), &horizons_id, &rotation);
+                state.test_particle = test_particle;
+                state.kepler_orbit = kepler_bodies.contains(s_entity);
+                state.ephemeris_driven = ephemeris_bodies.contains(s_entity);
+                state.zonal_harmonics = harmonics.copied().unwrap_or_default();
+                state.radiation_pressure = radiation.copied().unwrap_or_default();
+                state.atmosphere = atmosphere.copied().unwrap_or_default();
+            }
+            state.parent_state = parent_state;
+            if state.parent_state.is_none() {
+                state.element_input = false;
             }
             display_body_panel(egui_context.ctx_mut().unwrap(), state.as_mut(), &mut name, &mut pos, &mut vel, &mut mass, &mut diameter, &mut rotation_speed, &mut rotation, &mut model_path, &mut scene, &mut horizons_id, &mut commands, &systems, &assets, light, scene_query, billboard_material.as_mut(), &mut materials, &mut apply, &scale);
         }
@@ -134,7 +183,8 @@ fn initialize_state(
         ellipsoid: diameter.ellipsoid,
         orientation_id: anise_metadata.orientation_id,
         rotation_matrix: rotation.matrix,
-        target_id: anise_metadata.target_id
+        target_id: anise_metadata.target_id,
+        ..default()
     };
 }
 
@@ -198,14 +248,29 @@ fn display_body_properties(ui: &mut egui::Ui, state: &mut EditorPanelState) {
         ui.label("Mass (kg)");
         ui.add(egui::DragValue::new(&mut state.new_mass));
     });
+    ui.checkbox(&mut state.test_particle, "Test particle").on_hover_text("Feels the gravity of other bodies, but doesn't attract them");
+    ui.checkbox(&mut state.kepler_orbit, "Kepler orbit").on_hover_text("Follows a fixed two body orbit around its parent instead of taking part in the N-body simulation");
+    ui.checkbox(&mut state.ephemeris_driven, "Ephemeris driven").on_hover_text("Follows the loaded SPICE kernels (Ephemeris ID) instead of being integrated, but still attracts the other bodies");
     ui.horizontal(|ui| {
         ui.label("Rotation Speed (min/rotation)");
         ui.add(egui::DragValue::new(&mut state.new_rotation_speed));
     });
-    vector_field(ui, "Position (km)", &mut state.new_position);
-    vector_field(ui, "Velocity (km/s)", &mut state.new_velocity);
+    let had_element_input = state.element_input;
+    ui.add_enabled(state.parent_state.is_some(), egui::Checkbox::new(&mut state.element_input, "Orbital elements")).on_hover_text("Enter the starting state as an orbit around the parent body");
+    if state.element_input {
+        if !had_element_input {
+            state.elements = elements_from_vectors(state).unwrap_or_default();
+        }
+        orbital_elements(ui, state);
+    } else {
+        vector_field(ui, "Position (km)", &mut state.new_position);
+        vector_field(ui, "Velocity (km/s)", &mut state.new_velocity);
+    }
     rotation_matrix(ui, state);
     ellipsoid(ui, state);
+    gravity_field(ui, state);
+    radiation_pressure(ui, state);
+    atmosphere(ui, state);
 }
 
 fn ellipsoid(ui: &mut egui::Ui, state: &mut EditorPanelState) {
@@ -226,6 +291,150 @@ fn ellipsoid(ui: &mut egui::Ui, state: &mut EditorPanelState) {
     });
 }
 
+fn orbital_elements(ui: &mut egui::Ui, state: &mut EditorPanelState) {
+    let input = &mut state.elements;
+    let mut changed = false;
+    ui.vertical(|ui| {
+        ui.heading("Orbit");
+        ui.horizontal(|ui| {
+            ui.label("Semi-major axis (km)").on_hover_text("Negative for hyperbolic orbits");
+            changed |= ui.add(egui::DragValue::new(&mut input.semi_major_axis)).changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Eccentricity").on_hover_text("Parabolic orbits (1) have no semi-major axis, the value skips over them");
+            if ui.add(egui::DragValue::new(&mut input.eccentricity).speed(0.001).range(0.0..=f64::MAX).min_decimals(4)).changed() {
+                if (input.eccentricity - 1.0).abs() < PARABOLIC_MARGIN {
+                    input.eccentricity = if input.eccentricity < 1.0 { 1.0 - PARABOLIC_MARGIN } else { 1.0 + PARABOLIC_MARGIN };
+                }
+                changed = true;
+            }
+        });
+        for (label, value) in [("Inclination (°)", &mut input.inclination), ("Longitude of ascending node (°)", &mut input.ascending_node), ("Argument of periapsis (°)", &mut input.argument_of_periapsis)] {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                changed |= ui.add(egui::DragValue::new(value).speed(0.1)).changed();
+            });
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("anomaly_type").selected_text(if input.mean_anomaly { "Mean anomaly (°)" } else { "True anomaly (°)" }).show_ui(ui, |ui| {
+                changed |= ui.selectable_value(&mut input.mean_anomaly, false, "True anomaly (°)").changed();
+                changed |= ui.selectable_value(&mut input.mean_anomaly, true, "Mean anomaly (°)").changed();
+            });
+            changed |= ui.add(egui::DragValue::new(&mut input.anomaly).speed(0.1)).changed();
+        });
+    });
+    if changed {
+        state.elements.invalid = !vectors_from_elements(state);
+    }
+    if state.elements.invalid {
+        ui.colored_label(Color32::RED, "These elements don't describe a reachable orbit");
+    }
+    ui.label(format!("Position (km): {:.3}, {:.3}, {:.3}", state.new_position.x, state.new_position.y, state.new_position.z));
+    ui.label(format!("Velocity (km/s): {:.6}, {:.6}, {:.6}", state.new_velocity.x, state.new_velocity.y, state.new_velocity.z));
+}
+
+//Current starting state as elements relative to the parent, so switching the input mode starts from the same orbit
+fn elements_from_vectors(state: &EditorPanelState) -> Option<ElementInput> {
+    let (parent_position, parent_velocity, parent_mass) = state.parent_state?;
+    let elements = OrbitalElements::from_state(km_to_m_dvec(state.new_position) - parent_position, km_to_m_dvec(state.new_velocity) - parent_velocity, G * (parent_mass + state.new_mass))?;
+    Some(ElementInput {
+        semi_major_axis: elements.semi_major_axis / 1000.0,
+        eccentricity: elements.eccentricity,
+        inclination: elements.inclination.to_degrees(),
+        ascending_node: elements.ascending_node.to_degrees(),
+        argument_of_periapsis: elements.argument_of_periapsis.to_degrees(),
+        anomaly: elements.true_anomaly.to_degrees(),
+        ..default()
+    })
+}
+
+//Writes the absolute starting position and velocity, returns false if the elements have no state
+fn vectors_from_elements(state: &mut EditorPanelState) -> bool {
+    let Some((parent_position, parent_velocity, parent_mass)) = state.parent_state else {
+        return false;
+    };
+    let input = state.elements;
+    let anomaly = input.anomaly.to_radians();
+    let elements = OrbitalElements {
+        semi_major_axis: input.semi_major_axis * 1000.0,
+        eccentricity: input.eccentricity,
+        inclination: input.inclination.to_radians(),
+        ascending_node: input.ascending_node.to_radians(),
+        argument_of_periapsis: input.argument_of_periapsis.to_radians(),
+        true_anomaly: if input.mean_anomaly { true_anomaly_from_mean(anomaly, input.eccentricity) } else { anomaly },
+        ..default()
+    };
+    let Some((position, velocity)) = elements.to_state(G * (parent_mass + state.new_mass)) else {
+        return false;
+    };
+    state.new_position = m_to_km_dvec(parent_position + position);
+    state.new_velocity = m_to_km_dvec(parent_velocity + velocity);
+    true
+}
+
+fn gravity_field(ui: &mut egui::Ui, state: &mut EditorPanelState) {
+    ui.vertical(|ui| {
+        ui.heading("Gravity Field").on_hover_text("Zonal harmonics relative to the semi major equatorial radius, the pole is the z column of the rotation matrix");
+        ui.horizontal(|ui| {
+            ui.label("J2");
+            ui.add(egui::DragValue::new(&mut state.zonal_harmonics.j2).speed(1e-6).min_decimals(6));
+        });
+        ui.horizontal(|ui| {
+            ui.label("J3");
+            ui.add(egui::DragValue::new(&mut state.zonal_harmonics.j3).speed(1e-7).min_decimals(7));
+        });
+        ui.horizontal(|ui| {
+            ui.label("J4");
+            ui.add(egui::DragValue::new(&mut state.zonal_harmonics.j4).speed(1e-7).min_decimals(7));
+        });
+    });
+}
+
+fn radiation_pressure(ui: &mut egui::Ui, state: &mut EditorPanelState) {
+    ui.vertical(|ui| {
+        ui.heading("Radiation Pressure").on_hover_text("Pushed away from every enabled light source, an area of 0 disables it");
+        ui.horizontal(|ui| {
+            ui.label("Cross-sectional Area (m²)");
+            ui.add(egui::DragValue::new(&mut state.radiation_pressure.area).range(0.0..=f64::MAX));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Reflectivity").on_hover_text("1 absorbs all light, 2 reflects all of it back");
+            ui.add(egui::DragValue::new(&mut state.radiation_pressure.reflectivity).range(0.0..=2.0).speed(0.01));
+        });
+    });
+}
+
+fn atmosphere(ui: &mut egui::Ui, state: &mut EditorPanelState) {
+    ui.vertical(|ui| {
+        ui.heading("Atmosphere").on_hover_text("Exponential atmosphere that slows down the children of this body, leave a value at 0 to disable it");
+        ui.horizontal(|ui| {
+            ui.label("Base Density (kg/m³)").on_hover_text("Density at the reference altitude");
+            ui.add(egui::DragValue::new(&mut state.atmosphere.base_density).range(0.0..=f64::MAX).speed(0.001).min_decimals(15));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Reference Altitude (km)").on_hover_text("Altitude the exponential is fitted around, 0 for the surface");
+            let mut reference_altitude = state.atmosphere.reference_altitude / 1000.0;
+            if ui.add(egui::DragValue::new(&mut reference_altitude).range(0.0..=f64::MAX)).changed() {
+                state.atmosphere.reference_altitude = reference_altitude * 1000.0;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Scale Height (km)").on_hover_text("Height over which the density drops by a factor of e");
+            let mut scale_height = state.atmosphere.scale_height / 1000.0;
+            if ui.add(egui::DragValue::new(&mut scale_height).range(0.0..=f64::MAX)).changed() {
+                state.atmosphere.scale_height = scale_height * 1000.0;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Top (km)").on_hover_text("Altitude above which there is no drag");
+            let mut top = state.atmosphere.top / 1000.0;
+            if ui.add(egui::DragValue::new(&mut top).range(0.0..=f64::MAX)).changed() {
+                state.atmosphere.top = top * 1000.0;
+            }
+        });
+    });
+}
+
 fn rotation_matrix(ui: &mut egui::Ui, state: &mut EditorPanelState) {
     ui.vertical(|ui| {
         ui.heading("Rotation Matrix");
@@ -371,7 +580,7 @@ fn apply_changes(
     scale: &SimulationScale,
 ) {
     name.set(state.new_name.clone());
-    pos.current = km_to_m_dvec(state.new_position);
+    pos.set(km_to_m_dvec(state.new_position));
     vel.0 = km_to_m_dvec(state.new_velocity);
     mass.0 = state.new_mass;
     shape.applied = state.ellipsoid == shape.ellipsoid;
@@ -384,6 +593,36 @@ fn apply_changes(
         orientation_id: state.orientation_id,
         target_id: state.target_id,
     };
+    if state.test_particle {
+        commands.entity(state.entity.unwrap()).insert(TestParticle);
+    } else {
+        commands.entity(state.entity.unwrap()).remove::<TestParticle>();
+    }
+    if state.kepler_orbit {
+        commands.entity(state.entity.unwrap()).insert(KeplerOrbit::default());
+    } else {
+        commands.entity(state.entity.unwrap()).remove::<KeplerOrbit>();
+    }
+    if state.ephemeris_driven {
+        commands.entity(state.entity.unwrap()).insert(EphemerisDriven);
+    } else {
+        commands.entity(state.entity.unwrap()).remove::<EphemerisDriven>();
+    }
+    if state.zonal_harmonics.is_zero() {
+        commands.entity(state.entity.unwrap()).remove::<ZonalHarmonics>();
+    } else {
+        commands.entity(state.entity.unwrap()).insert(state.zonal_harmonics);
+    }
+    if state.radiation_pressure.is_zero() {
+        commands.entity(state.entity.unwrap()).remove::<RadiationPressure>();
+    } else {
+        commands.entity(state.entity.unwrap()).insert(state.radiation_pressure);
+    }
+    if state.atmosphere.is_zero() {
+        commands.entity(state.entity.unwrap()).remove::<Atmosphere>();
+    } else {
+        commands.entity(state.entity.unwrap()).insert(state.atmosphere);
+    }
     if let Some((mut light, mut source, mut visible)) = light {
         light.color = state.new_light_settings.as_ref().unwrap().color;
         if let Some(material) = billboard_material {
@@ -0,0 +1,85 @@
+use crate::simulation::scenario::setup::ScenarioData;
+use crate::simulation::ui::bottom_bar::get_date_from_seconds;
+use crate::simulation::ui::system_panel::system_panel;
+use crate::simulation::ui::UiState;
+use crate::simulation::SimState;
+use bevy::app::{App, Plugin};
+use bevy::prelude::{in_state, IntoScheduleConfigs, OnExit, Res, ResMut, Resource};
+use bevy_egui::egui::RichText;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+
+pub struct EventLogPlugin;
+
+impl Plugin for EventLogPlugin {
+
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<EventLog>()
+            .add_systems(OnExit(SimState::Loaded), clear_event_log)
+            .add_systems(EguiPrimaryContextPass, (event_log_window.after(system_panel)).run_if(in_state(SimState::Loaded)));
+    }
+
+}
+
+//Things that happened during the simulation (collisions, ...), shown in the event log window
+#[derive(Resource, Default)]
+pub struct EventLog {
+
+    pub entries: Vec<LoggedEvent>,
+
+}
+
+#[derive(Clone)]
+pub struct LoggedEvent {
+
+    pub time: f64, //simulated seconds since the start of the scenario
+    pub message: String,
+
+}
+
+impl EventLog {
+
+    pub fn add(&mut self, time: f64, message: String) {
+        self.entries.push(LoggedEvent { time, message });
+    }
+
+}
+
+fn clear_event_log(
+    mut event_log: ResMut<EventLog>
+) {
+    event_log.entries.clear();
+}
+
+fn event_log_window(
+    mut egui_ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut event_log: ResMut<EventLog>,
+    scenario_data: Res<ScenarioData>
+) {
+    if !ui_state.visible || egui_ctx.ctx_mut().is_err() {
+        return;
+    }
+    egui::Window::new("Event Log")
+        .open(&mut ui_state.show_events)
+        .collapsible(true)
+        .constrain(true)
+        .scroll([false, true])
+        .default_width(350.0)
+        .show(egui_ctx.ctx_mut().unwrap(), |ui| {
+            if event_log.entries.is_empty() {
+                ui.label("Nothing happened yet");
+            }
+            for event in event_log.entries.iter().rev() {
+                let date = get_date_from_seconds(scenario_data.starting_time_millis, event.time);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(date.format("%d.%m.%Y %H:%M:%S").to_string()).strong());
+                    ui.label(&event.message);
+                });
+            }
+            ui.separator();
+            if ui.button("Clear").clicked() {
+                event_log.entries.clear();
+            }
+        });
+}
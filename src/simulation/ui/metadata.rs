@@ -1,15 +1,21 @@
 use crate::simulation::components::anise::AlmanacHolder;
+use crate::simulation::components::body::{Mass, SimPosition, TestParticle, Velocity};
+use crate::simulation::components::collision::{CollisionMode, CollisionSettings};
+use crate::simulation::components::editor::{EditorSystemType, EditorSystems};
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::speed::Speed;
+use crate::simulation::integration::ForceSettings;
+use crate::simulation::scenario::barycenter::barycenter;
 use crate::simulation::scenario::loading::LoadingState;
 use crate::simulation::scenario::setup::ScenarioData;
 use crate::simulation::ui::bottom_bar::get_date_from_seconds;
+use crate::simulation::ui::editor_body_panel::EditorPanelState;
 use crate::simulation::ui::toast::{error_toast, success_toast, ToastContainer};
 use crate::simulation::units::text_formatter::format_seconds;
 use crate::utils::sim_state_type_editor;
 use anise::almanac::Almanac;
 use bevy::app::{App, Plugin};
-use bevy::prelude::{IntoScheduleConfigs, Local, ResMut, Resource};
+use bevy::prelude::{Commands, Has, IntoScheduleConfigs, Local, Query, Res, ResMut, Resource};
 use bevy_async_task::TaskRunner;
 use bevy_egui::egui::{Button, ComboBox};
 use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
@@ -51,7 +57,13 @@ fn metadata_editor(
     mut almanac_holder: ResMut<AlmanacHolder>,
     mut task_executor: TaskRunner<Result<(Almanac, String), String>>,
     mut loading_state: ResMut<LoadingState>,
-    mut loading: Local<bool>
+    mut loading: Local<bool>,
+    mut forces: ResMut<ForceSettings>,
+    mut collisions: ResMut<CollisionSettings>,
+    mut bodies: Query<(&Mass, &mut SimPosition, &mut Velocity, Has<TestParticle>)>,
+    mut editor_state: ResMut<EditorPanelState>,
+    systems: Res<EditorSystems>,
+    mut commands: Commands
 ) {
     let mut show = state.show;
     let mut selected_spk_file = state.selected_spk_file.clone();
@@ -67,7 +79,12 @@ fn metadata_editor(
             ui.heading("Basic Information");
             edit_basic_info(ui, &mut scenario_data);
             edit_starting_time(ui, &mut scenario_data);
-            edit_simulation_settings(ui, &mut scale, &mut speed);
+            edit_simulation_settings(ui, &mut scale, &mut speed, &mut forces, &mut collisions);
+            if edit_barycenter(ui, &mut scenario_data, &mut bodies, &mut toasts) {
+                //the body panel would write the old starting data back on apply
+                editor_state.entity = None;
+                commands.run_system(systems.0[EditorSystemType::UPDATE_POSITIONS]);
+            }
             edit_spk_files(ui, &mut scenario_data, &mut selected_spk_file, &mut new_spk_file, &mut toasts, &mut almanac_holder, &mut task_executor, &mut loading_state, &mut loading);
         });
 
@@ -114,17 +131,59 @@ fn edit_starting_time(ui: &mut egui::Ui, scenario_data: &mut ScenarioData) {
     }
 }
 
-fn edit_simulation_settings(ui: &mut egui::Ui, scale: &mut SimulationScale, speed: &mut Speed) {
+fn edit_simulation_settings(ui: &mut egui::Ui, scale: &mut SimulationScale, speed: &mut Speed, forces: &mut ForceSettings, collisions: &mut CollisionSettings) {
     ui.horizontal(|ui| {
-        ui.label("Default Timestep (in seconds)");
+        ui.label("Default Speed per Substep (s/s)").on_hover_text("Simulated seconds per real second that every substep adds");
         ui.add(egui::DragValue::new(&mut speed.0));
-        ui.label(format!("({}/step)", format_seconds(speed.0)));
+        ui.label(format!("({}/substep)", format_seconds(speed.step())));
     });
     ui.horizontal(|ui| {
         ui.label("Simulation Scale").on_hover_text("Only applied on simulation start");
         ui.add(egui::DragValue::new(&mut scale.0).min_decimals(20));
     });
     ui.label(format!("(1m = {} units)", 1. / scale.0));
+    ui.horizontal(|ui| {
+        ui.label("Softening Length (km)").on_hover_text("Keeps the gravity between very close bodies finite, 0 disables it");
+        let mut softening = forces.softening / 1000.0;
+        if ui.add(egui::DragValue::new(&mut softening).range(0.0..=f64::MAX)).changed() {
+            forces.softening = softening * 1000.0;
+        }
+    });
+    ui.checkbox(&mut forces.relativity, "General Relativity (1PN)").on_hover_text("Post-Newtonian correction for the bodies orbiting the most massive body, makes orbits precess like Mercury's");
+    ui.horizontal(|ui| {
+        ui.label("Collisions").on_hover_text("What happens when two bodies touch");
+        ComboBox::from_id_salt("collision_mode").selected_text(collisions.mode.as_str()).show_ui(ui, |ui| {
+            for mode in CollisionMode::all() {
+                ui.selectable_value(&mut collisions.mode, mode, mode.as_str());
+            }
+        });
+    });
+    ui.checkbox(&mut collisions.roche_breakup, "Roche Limit Breakup").on_hover_text("Bodies that cross the Roche limit of their parent break up into fragments along their orbit");
+}
+
+//Returns true if the bodies were moved into the barycentric frame
+fn edit_barycenter(
+    ui: &mut egui::Ui,
+    scenario_data: &mut ScenarioData,
+    bodies: &mut Query<(&Mass, &mut SimPosition, &mut Velocity, Has<TestParticle>)>,
+    toasts: &mut ToastContainer
+) -> bool {
+    ui.checkbox(&mut scenario_data.barycentric_correction, "Barycentric Correction").on_hover_text("Moves the barycenter to the origin and removes the total momentum when the simulation is loaded, so the system doesn't drift away");
+    if !ui.button("Move Bodies to Barycentric Frame").on_hover_text("Does the same to the starting data of the bodies right now").clicked() {
+        return false;
+    }
+    let offset = barycenter(bodies.iter().filter(|(_, _, _, test_particle)| !test_particle).map(|(mass, position, velocity, _)| (mass.0, position.current, velocity.0)));
+    let Some((position, velocity)) = offset else {
+        toasts.0.add(error_toast("There are no bodies with mass"));
+        return false;
+    };
+    for (_, mut body_position, mut body_velocity, _) in bodies.iter_mut() {
+        let new_position = body_position.current - position;
+        body_position.set(new_position);
+        body_velocity.0 -= velocity;
+    }
+    toasts.0.add(success_toast("Moved the bodies to the barycentric frame"));
+    true
 }
 
 fn edit_spk_files(
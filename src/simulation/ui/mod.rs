@@ -10,6 +10,8 @@ pub mod editor_bottom_bar;
 pub mod toast;
 pub mod metadata;
 pub mod menu;
+pub mod event_log;
+pub mod divergence;
 
 //use crate::fps::Fps;
 //use crate::fps::Fps;
@@ -22,8 +24,10 @@ pub mod menu;
 use crate::simulation::integration::SimulationStep;
 //use crate::fps::Fps;
 use crate::simulation::ui::debug_window::DebugPlugin;
+use crate::simulation::ui::divergence::DivergencePlugin;
 use crate::simulation::ui::editor_body_panel::{editor_body_panel, EditorPanelState};
 use crate::simulation::ui::editor_bottom_bar::editor_bottom_bar;
+use crate::simulation::ui::event_log::EventLogPlugin;
 use crate::simulation::ui::metadata::MetadataPlugin;
 use crate::simulation::ui::scenario_selection::ScenarioSelectionPlugin;
 use crate::simulation::ui::sim_body_panel::sim_body_panel;
@@ -45,7 +49,7 @@ use bevy::{
 use bevy_egui::EguiPrimaryContextPass;
 
 #[derive(Resource, Reflect, Default)]
-pub struct SimTime(pub f32);
+pub struct SimTime(pub f64); //simulated seconds, f64 so the epoch stays exact over long runs
 
 #[derive(Resource, Reflect, Default)]
 pub struct Light {
@@ -65,6 +69,8 @@ pub struct UiState {
     pub step_type: StepType,
     pub show_debug: bool,
     pub show_keys: bool,
+    pub show_events: bool,
+    pub show_divergence: bool,
     pub edit_mass: bool,
     pub vel_multiplier: f64,
     pub mass_value: f64,
@@ -89,6 +95,8 @@ impl Plugin for InterfacePlugin {
             .add_plugins(ScenarioSelectionPlugin)
             .add_plugins(ToastPlugin)
             .add_plugins(MetadataPlugin)
+            .add_plugins(EventLogPlugin)
+            .add_plugins(DivergencePlugin)
             .add_systems(
                 EguiPrimaryContextPass,
                 (
@@ -1,15 +1,16 @@
 use crate::simulation::asset::from_scenario_source;
 use crate::simulation::asset::serialization::SimulationData;
+use crate::simulation::components::collision::CollisionMode;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::speed::Speed;
-use crate::simulation::integration::IntegrationType;
+use crate::simulation::integration::{AdaptiveTolerance, ForceModel, ForceSettings, IntegrationType};
 use crate::simulation::ui::toast::{error_toast, ToastContainer};
 use crate::simulation::{SimState, SimStateType};
 use bevy::app::{App, Plugin};
 use bevy::asset::LoadedFolder;
 use bevy::platform::collections::HashMap;
 use bevy::prelude::{in_state, AssetServer, Assets, Commands, Handle, Image, IntoScheduleConfigs, Local, NextState, OnEnter, Res, ResMut, Resource, State};
-use bevy_egui::egui::{Align, CentralPanel, ComboBox, Layout, SidePanel, TextureId};
+use bevy_egui::egui::{Align, CentralPanel, ComboBox, Layout, SidePanel, TextEdit, TextureId, Ui};
 use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass, EguiTextureHandle};
 use std::fs;
 
@@ -113,7 +114,12 @@ fn creation_sidebar(
                             description: selection_state.description.clone(),
                             scale: SimulationScale::default().0,
                             timestep: Speed::default().0 as i32,
-                            data_sets: Vec::new()
+                            data_sets: Vec::new(),
+                            softening: 0.0,
+                            collisions: CollisionMode::default(),
+                            roche_breakup: false,
+                            relativity: false,
+                            barycentric_correction: false
                         };
                         create_scenario(selection_state.file_name.clone(), selection_state.image_path.clone(), initial_data);
                         selection_state.show_creation = false;
@@ -161,7 +167,9 @@ fn show_menu(
     mut scale: ResMut<SimulationScale>,
     mut speed: ResMut<Speed>,
     mut next_integrator: ResMut<NextState<IntegrationType>>,
-    integrator: Res<State<IntegrationType>>
+    integrator: Res<State<IntegrationType>>,
+    mut tolerance: ResMut<AdaptiveTolerance>,
+    mut forces: ResMut<ForceSettings>
 ) {
     CentralPanel::default()
         .show(&egui_context.ctx_mut().unwrap().clone(), |ui| {
@@ -185,6 +193,20 @@ fn show_menu(
                 if new_integrator != **integrator {
                     next_integrator.set(new_integrator);
                 }
+                if **integrator == IntegrationType::DormandPrince {
+                    ui.separator();
+                    tolerance_field(ui, "Relative tolerance", &mut tolerance.relative);
+                    tolerance_field(ui, "Absolute tolerance", &mut tolerance.absolute);
+                }
+                ui.separator();
+                ComboBox::from_label("Force model").selected_text(forces.model.as_str()).show_ui(ui, |ui| {
+                    for model in ForceModel::all() {
+                        ui.selectable_value(&mut forces.model, model, model.as_str());
+                    }
+                });
+                if forces.model == ForceModel::BarnesHut {
+                    ui.add(egui::Slider::new(&mut forces.theta, 0.0..=1.5).text("Opening angle θ"));
+                }
                 ui.separator();
                 ui.checkbox(&mut selection_state.auto_load_spk, "Auto load SPK files");
             });
@@ -255,6 +277,21 @@ fn show_menu(
         });
 }
 
+fn tolerance_field(ui: &mut Ui, name: &str, value: &mut f64) {
+    ui.label(name);
+    let mut new_value = format!("{:e}", value);
+    if ui
+        .add(TextEdit::singleline(&mut new_value).desired_width(60.0))
+        .changed()
+    {
+        if let Ok(new_value_num) = new_value.parse::<f64>() {
+            if new_value_num > 0.0 {
+                *value = new_value_num;
+            }
+        }
+    }
+}
+
 fn select_scenario(
     selected_scenario: &mut ResMut<SelectedScenario>,
     sim_state: &mut ResMut<NextState<SimState>>,
@@ -1,17 +1,22 @@
 use crate::constants::{G, M_TO_AU};
-use crate::simulation::components::apsis::ApsisBody;
+use crate::simulation::components::apsis::{Apsis, ApsisBody, Precession};
 use crate::simulation::components::body::{BodyChildren, BodyParent, BodyShape, Mass, OrbitSettings, RotationSpeed, SimPosition, Velocity};
 use crate::simulation::components::horizons::AniseMetadata;
+use crate::simulation::components::orbital_elements::OrbitalElements;
 use crate::simulation::components::scale::SimulationScale;
 use crate::simulation::components::selection::SelectedEntity;
+use crate::simulation::components::sphere_of_influence::SphereOfInfluence;
 use crate::simulation::ui::components::{body_multiplier_field, body_property_field, copy_value_button};
+use crate::simulation::ui::divergence::Divergence;
 use crate::simulation::ui::toast::ToastContainer;
-use crate::simulation::ui::UiState;
+use crate::simulation::scenario::setup::ScenarioData;
+use crate::simulation::ui::bottom_bar::get_date_from_seconds;
+use crate::simulation::ui::{SimTime, UiState};
 use crate::simulation::units::text_formatter::{format_length, format_seconds};
 use bevy::color::Srgba;
 use bevy::ecs::system::SystemParam;
 use bevy::prelude::{Camera, Commands, Entity, Mut, Name, Query, Res, ResMut, Transform, Without};
-use bevy_egui::egui::{RichText, ScrollArea, SliderClamping, Ui};
+use bevy_egui::egui::{Color32, RichText, ScrollArea, SliderClamping, Ui};
 use bevy_egui::{egui, EguiContexts};
 
 #[derive(SystemParam)]
@@ -23,9 +28,12 @@ pub struct SimBodyPanelSet<'w, 's> {
     selected_entity: Res<'w, SelectedEntity>,
     ui_state: ResMut<'w, UiState>,
     s_scale: Res<'w, SimulationScale>,
-   // scenario: Res<'w, ScenarioData>,
-    // sim_time: Res<'w, SimTime>,
-    toast_container: ResMut<'w, ToastContainer>
+    scenario: Res<'w, ScenarioData>,
+    sim_time: Res<'w, SimTime>,
+    toast_container: ResMut<'w, ToastContainer>,
+    divergence: Res<'w, Divergence>,
+    elements: Query<'w, 's, &'static OrbitalElements>,
+    spheres: Query<'w, 's, &'static SphereOfInfluence>
 }
 
 pub fn sim_body_panel(
@@ -76,16 +84,35 @@ pub fn sim_body_panel(
                             // Velocity Orbit Velocity around parent
                             velocity_section(ui, velocity, &parent, &mut set.ui_state);
 
+                            let elements = set.elements.get(entity).ok();
                             let mut new_apsis = None;
-                            if let Some((_, _, _, p_mass,_ )) = parent {
+                            if let Some((p_pos, _, _, p_mass,_ )) = parent {
                                 if let Some(apsis) = apsis {
-                                    let distance = ((apsis.aphelion.distance + apsis.perihelion.distance) / 2.0) as f64;
-                                    orbit.period = 2.0 * std::f64::consts::PI * f64::sqrt(f64::powf(distance, 3.0) / (G * (p_mass.0 + mass.0)));
+                                    //unbound orbits have no period, the circular one at the current distance still sizes the motion line
+                                    orbit.period = match elements.and_then(|e| e.period) {
+                                        Some(period) => period,
+                                        None => {
+                                            let distance = p_pos.current.distance(pos.current);
+                                            2.0 * std::f64::consts::PI * f64::sqrt(f64::powf(distance, 3.0) / (G * (p_mass.0 + mass.0)))
+                                        }
+                                    };
                                     ui.label(RichText::new("Orbital Period").size(16.0).underline());
                                     ui.label(format!("{}", format_seconds(orbit.period)));
                                     new_apsis = Some(apsis);
                                 }
                             }
+                            if let (Some(elements), Some((_, _, p_name, _, _))) = (elements, &parent) {
+                                elements_section(ui, elements, p_name.as_str());
+                            }
+                            if let Ok(sphere) = set.spheres.get(entity) {
+                                sphere_of_influence_section(ui, sphere, orbit);
+                            }
+
+                            if let Some(entry) = set.divergence.get(entity) {
+                                ui.label(RichText::new("Ephemeris Error").size(16.0).underline());
+                                ui.label(format!("Position: {}", format_length(entry.position)));
+                                ui.label(format!("Velocity: {:.3} m/s", entry.velocity));
+                            }
 
                             shape_section(ui, diameter);
 
@@ -100,7 +127,7 @@ pub fn sim_body_panel(
 
                             // Distance to parent
                             if let Some((parent_pos, _, p_name, _, _)) = parent {
-                                orbit_section(ui, pos, parent_pos, orbit, p_name.as_str(), new_apsis);
+                                orbit_section(ui, parent_pos.current.distance(pos.current), orbit, p_name.as_str(), new_apsis, set.scenario.starting_time_millis, set.sim_time.0);
                             }
 
                             if s_children.iter().count() > 0 {
@@ -187,24 +214,22 @@ fn shape_section(ui: &mut Ui, shape: &BodyShape) {
     });
 }
 
-fn orbit_section(ui: &mut Ui, pos: &SimPosition, parent_pos: &SimPosition, orbit: &mut OrbitSettings, p_name: &str, new_apsis: Option<Mut<ApsisBody>>) {
+fn orbit_section(ui: &mut Ui, distance_in_m: f64, orbit: &mut OrbitSettings, p_name: &str, new_apsis: Option<Mut<ApsisBody>>, start: i64, time: f64) {
     ui.label(RichText::new(format!("Distance to {} (Center)", p_name)).size(16.0).underline());
-    let distance_in_m = parent_pos.current.distance(pos.current);
-    ui.label(format!("{}", format_length(distance_in_m as f32)));
+    ui.label(format!("{}", format_length(distance_in_m)));
     ui.label(format!("{:.3} au", distance_in_m * (M_TO_AU as f64)));
 
     if let Some(mut apsis) = new_apsis {
         //Apsis
-        ui.label(RichText::new(format!("Periapsis ({})", p_name)).size(16.0).underline());
-        ui.label(format!("{}", format_length(apsis.perihelion.distance)));
-        ui.label(format!("{:.3} au", apsis.perihelion.distance * M_TO_AU));
+        apsis_section(ui, format!("Periapsis ({})", p_name).as_str(), apsis.periapsis, apsis.next_periapsis, apsis.last_periapsis, start, time);
+        apsis_section(ui, format!("Apoapsis ({})", p_name).as_str(), apsis.apoapsis, apsis.next_apoapsis, apsis.last_apoapsis, start, time);
 
-        ui.label(RichText::new(format!("Apoapsis ({})", p_name)).size(16.0).underline());
-        ui.label(format!("{}", format_length(apsis.aphelion.distance)));
-        ui.label(format!("{:.3} au", apsis.aphelion.distance * M_TO_AU));
-        if ui.button("Reset Apsides").clicked() {
-            apsis.aphelion.distance = 0.0;
-            apsis.perihelion.distance = 0.0;
+        if let Some(rate) = apsis.precession.arcseconds_per_century() {
+            ui.label(RichText::new("Periapsis Precession").size(16.0).underline());
+            ui.label(format!("{:.2}\"/century ({} passages)", rate, apsis.precession.passages));
+        }
+        if ui.button("Reset Precession").clicked() {
+            apsis.precession = Precession::default();
         }
 
         let mut new_draw_lines = orbit.draw_lines;
@@ -215,8 +240,57 @@ fn orbit_section(ui: &mut Ui, pos: &SimPosition, parent_pos: &SimPosition, orbit
                 orbit.lines.clear();
             }
         }
-        ui.checkbox(&mut false, "Draw Orbit (TODO)");
+        ui.checkbox(&mut orbit.draw_orbit, "Draw Orbit");
+    }
+}
+
+fn apsis_section(ui: &mut Ui, title: &str, apsis: Option<Apsis>, next: Option<f64>, last: Option<f64>, start: i64, time: f64) {
+    ui.label(RichText::new(title).size(16.0).underline());
+    let Some(apsis) = apsis else {
+        ui.label("None (unbound orbit)");
+        return;
+    };
+    ui.label(format!("{}", format_length(apsis.distance)));
+    ui.label(format!("{:.3} au", apsis.distance * M_TO_AU as f64));
+    if let Some(next) = next {
+        ui.label(format!("Next in {}", format_seconds(next)));
+    }
+    if let Some(last) = last {
+        ui.label(format!("Last passed {} ({} ago)", get_date_from_seconds(start, last).format("%d.%m.%Y %H:%M"), format_seconds(time - last)));
+    }
+}
+
+fn elements_section(ui: &mut Ui, elements: &OrbitalElements, p_name: &str) {
+    ui.label(RichText::new(format!("Orbital Elements ({})", p_name)).size(16.0).underline());
+    ui.vertical(|ui| {
+        ui.label(format!("Semi-major axis: {}", semi_major_axis_text(elements.semi_major_axis)));
+        ui.label(format!("Eccentricity: {:.6}", elements.eccentricity));
+        ui.label(format!("Inclination: {:.4}°", elements.inclination.to_degrees()));
+        ui.label(format!("Longitude of ascending node: {:.4}°", elements.ascending_node.to_degrees()));
+        ui.label(format!("Argument of periapsis: {:.4}°", elements.argument_of_periapsis.to_degrees()));
+        ui.label(format!("True anomaly: {:.4}°", elements.true_anomaly.to_degrees()));
+        ui.label(format!("Mean anomaly: {:.4}°", elements.mean_anomaly.to_degrees()));
+    });
+}
+
+fn semi_major_axis_text(semi_major_axis: f64) -> String {
+    if semi_major_axis.is_infinite() {
+        "∞ (parabolic)".to_string()
+    } else if semi_major_axis < 0.0 {
+        format!("{} (hyperbolic)", format_length(semi_major_axis))
+    } else {
+        format_length(semi_major_axis)
+    }
+}
+
+fn sphere_of_influence_section(ui: &mut Ui, sphere: &SphereOfInfluence, orbit: &mut OrbitSettings) {
+    ui.label(RichText::new("Sphere of Influence").size(16.0).underline());
+    ui.label(format!("Hill radius: {}", format_length(sphere.hill))).on_hover_text("Satellites inside stay bound to this body");
+    ui.label(format!("Laplace radius: {}", format_length(sphere.laplace))).on_hover_text("This body dominates the motion over its parent inside");
+    if sphere.leaves_parent {
+        ui.colored_label(Color32::YELLOW, "The orbit leaves the sphere of influence of the parent");
     }
+    ui.checkbox(&mut orbit.draw_soi, "Draw Sphere of Influence");
 }
 
 fn mass_section(ui: &mut Ui, mass: &mut Mass, ui_state: &mut UiState, toast_container: &mut ToastContainer) {
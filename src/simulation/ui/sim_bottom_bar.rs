@@ -1,19 +1,20 @@
+use crate::constants::PHYSICS_TICK_RATE;
 use crate::simulation::components::lock_on::LockOn;
 use crate::simulation::components::speed::Speed;
 use crate::simulation::integration::{Pause, SubSteps};
 use crate::simulation::scenario::setup::scenario::ScenarioData;
 use crate::simulation::ui::bottom_bar::get_date_from_seconds;
 use crate::simulation::ui::{SimTime, StepType, UiState};
-use crate::simulation::{SimState, SimStateType};
+use crate::simulation::units::text_formatter::format_seconds;
+use crate::simulation::SimState;
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
-use bevy::prelude::{NextState, Query, Res, ResMut, Time, Window};
+use bevy::prelude::{NextState, Query, Res, ResMut, Window};
 use bevy::window::PresentMode;
 use bevy_egui::egui::TextEdit;
 use bevy_egui::{egui, EguiContexts};
 
 pub fn simulation_bottom_bar(
-    time: Res<Time>,
-    mut sim_time: ResMut<SimTime>,
+    sim_time: Res<SimTime>,
     mut egui_context: EguiContexts,
     mut speed: ResMut<Speed>,
     mut windows: Query<&mut Window>,
@@ -24,14 +25,10 @@ pub fn simulation_bottom_bar(
     mut sub_steps: ResMut<SubSteps>,
     mut ui_state: ResMut<UiState>,
     diagnostics: Res<DiagnosticsStore>,
-    sim_type: Res<SimStateType>
 ) {
     if !ui_state.visible || windows.is_empty() || egui_context.ctx_mut().is_err() {
         return;
     }
-    if !pause.0 && *sim_type == SimStateType::Simulation {
-        sim_time.0 += time.delta_secs() * (speed.0 * sub_steps.0 as f64) as f32;
-    }
     let date = get_date_from_seconds(scenario_data.starting_time_millis, sim_time.0);
     let mut window = windows.single_mut().unwrap();
     egui::TopBottomPanel::bottom("time_panel")
@@ -83,7 +80,10 @@ pub fn simulation_bottom_bar(
                         }
                         //       ui.add_space(20.0);
 
-                        if ui.toggle_value(&mut !timestep_selected, "Substeps per frame").clicked() {
+                        if ui.toggle_value(&mut !timestep_selected, "Substeps per physics step")
+                            .on_hover_text(format!("Every one of the {} physics steps per second is split into this many integration steps", PHYSICS_TICK_RATE))
+                            .clicked()
+                        {
                             timestep_selected = false;
                         }
                         let mut new_sub_steps = sub_steps.0.to_string();
@@ -96,7 +96,10 @@ pub fn simulation_bottom_bar(
                             }
                         }
                         //     ui.add_space(20.0);
-                        if ui.toggle_value(&mut timestep_selected, "Timestep in seconds").clicked() {
+                        if ui.toggle_value(&mut timestep_selected, "Speed per substep (s/s)")
+                            .on_hover_text("Simulated seconds per real second that every substep adds")
+                            .clicked()
+                        {
                             timestep_selected = true;
                         }
                         let mut new_speed = speed.0.to_string();
@@ -108,7 +111,7 @@ pub fn simulation_bottom_bar(
                                 speed.0 = new_speed_num;
                             }
                         }
-                        ui.label(format!("({})", speed.format(1)));
+                        ui.label(format!("({}/substep)", format_seconds(speed.step())));
 
                         if timestep_selected {
                             ui_state.step_type = StepType::TIMESTEPS
@@ -195,6 +195,16 @@ pub fn system_panel(
                         if ui.button("Open Keybind Window").clicked() {
                             ui_state.show_keys = true;
                         }
+                        if *system_panel_set.sim_state_type == SimStateType::Simulation {
+                            ui.add_space(5.0);
+                            if ui.button("Open Event Log").clicked() {
+                                ui_state.show_events = true;
+                            }
+                            ui.add_space(5.0);
+                            if ui.button("Open Ephemeris Divergence").clicked() {
+                                ui_state.show_divergence = true;
+                            }
+                        }
                         ui.add_space(5.0);
                         if *system_panel_set.sim_state_type == SimStateType::Editor && ui.button("Edit metadata").clicked() {
                             system_panel_set.show_metadata.show = true;
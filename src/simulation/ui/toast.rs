@@ -51,6 +51,16 @@ pub fn error_toast(text: &str) -> Toast {
     }
 }
 
+pub fn warning_toast(text: &str) -> Toast {
+    Toast {
+        text: text.into(),
+        kind: ToastKind::Warning,
+        options: ToastOptions::default()
+            .duration_in_seconds(5.0),
+        ..default()
+    }
+}
+
 pub fn important_error_toast(text: &str) -> Toast {
     Toast {
         text: text.into(),
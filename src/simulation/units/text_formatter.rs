@@ -25,11 +25,12 @@ pub fn format_seconds(seconds: f64) -> String {
     }
 }
 
-pub fn format_length(distance: f32) -> String {
-    let kilometers = distance / 1000.0;
-    if kilometers < 1_000_000.0 {
+//Takes f32 and f64, negative lengths are formatted like positive ones
+pub fn format_length(distance: impl Into<f64>) -> String {
+    let kilometers = distance.into() / 1000.0;
+    if kilometers.abs() < 1_000_000.0 {
         return format!("{:.2} km", kilometers);
-    } else if kilometers < 1_000_000_000.0 {
+    } else if kilometers.abs() < 1_000_000_000.0 {
         let millions = kilometers / 1_000_000.0;
         return format!("{:.2} million km", millions);
     } else {